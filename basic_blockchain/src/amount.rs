@@ -0,0 +1,130 @@
+use std::fmt;
+use std::str::FromStr;
+
+use thiserror::Error;
+
+pub const DECIMALS: u32 = 8;
+const UNITS_PER_COIN: u64 = 10u64.pow(DECIMALS);
+
+/// A currency amount stored as a whole number of the smallest unit, one
+/// hundred-millionth of a coin. Serialized as that integer, so hashes never
+/// depend on float formatting.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(transparent)]
+pub struct Amount(u64);
+
+#[derive(Error, Debug, PartialEq)]
+pub enum AmountError {
+    #[error("Amount must be a non-negative number")]
+    Invalid,
+    #[error("Amount has more than {} decimal places", DECIMALS)]
+    TooPrecise,
+    #[error("Amount is too large")]
+    Overflow,
+}
+
+impl Amount {
+    pub const ZERO: Amount = Amount(0);
+
+    pub fn coins(coins: u64) -> Amount {
+        Amount(coins * UNITS_PER_COIN)
+    }
+
+    pub fn units(self) -> u64 {
+        self.0
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn checked_add(self, other: Amount) -> Option<Amount> {
+        self.0.checked_add(other.0).map(Amount)
+    }
+
+    pub fn checked_sub(self, other: Amount) -> Option<Amount> {
+        self.0.checked_sub(other.0).map(Amount)
+    }
+
+    /// Sums `amounts`, or `None` if the total overflows.
+    pub fn checked_sum<'a, I: IntoIterator<Item = &'a Amount>>(amounts: I) -> Option<Amount> {
+        amounts.into_iter().try_fold(Amount::ZERO, |total, a| total.checked_add(*a))
+    }
+}
+
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let whole = self.0 / UNITS_PER_COIN;
+        let frac = self.0 % UNITS_PER_COIN;
+        if frac == 0 {
+            return write!(f, "{}", whole);
+        }
+        let frac = format!("{:0width$}", frac, width = DECIMALS as usize);
+        write!(f, "{}.{}", whole, frac.trim_end_matches('0'))
+    }
+}
+
+impl FromStr for Amount {
+    type Err = AmountError;
+
+    fn from_str(s: &str) -> Result<Amount, AmountError> {
+        let s = s.trim();
+        let (whole, frac) = match s.split_once('.') {
+            Some((whole, frac)) => (whole, frac),
+            None => (s, ""),
+        };
+        if whole.is_empty() && frac.is_empty() {
+            return Err(AmountError::Invalid);
+        }
+        let digits = |part: &str| part.chars().all(|c| c.is_ascii_digit());
+        if !digits(whole) || !digits(frac) {
+            return Err(AmountError::Invalid);
+        }
+        if frac.len() > DECIMALS as usize {
+            return Err(AmountError::TooPrecise);
+        }
+
+        let whole = match whole {
+            "" => 0,
+            whole => whole.parse::<u64>().map_err(|_| AmountError::Overflow)?,
+        };
+        let frac = format!("{:0<width$}", frac, width = DECIMALS as usize)
+            .parse::<u64>()
+            .map_err(|_| AmountError::Invalid)?;
+        whole
+            .checked_mul(UNITS_PER_COIN)
+            .and_then(|units| units.checked_add(frac))
+            .map(Amount)
+            .ok_or(AmountError::Overflow)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_display() {
+        assert_eq!("12.5".parse(), Ok(Amount(1_250_000_000)));
+        assert_eq!(".00000001".parse(), Ok(Amount(1)));
+        assert_eq!("100".parse(), Ok(Amount::coins(100)));
+        assert_eq!("1.000000001".parse::<Amount>(), Err(AmountError::TooPrecise));
+        assert_eq!("-1".parse::<Amount>(), Err(AmountError::Invalid));
+        assert_eq!("1e3".parse::<Amount>(), Err(AmountError::Invalid));
+        assert_eq!(".".parse::<Amount>(), Err(AmountError::Invalid));
+        assert_eq!("999999999999".parse::<Amount>(), Err(AmountError::Overflow));
+
+        assert_eq!(Amount(1_250_000_000).to_string(), "12.5");
+        assert_eq!(Amount(1).to_string(), "0.00000001");
+        assert_eq!(Amount::coins(3).to_string(), "3");
+    }
+
+    #[test]
+    fn test_checked_arithmetic() {
+        let max = Amount(u64::MAX);
+        assert_eq!(max.checked_add(Amount(1)), None);
+        assert_eq!(Amount::ZERO.checked_sub(Amount(1)), None);
+        assert_eq!(Amount::checked_sum(&[Amount::coins(1), Amount::coins(2)]), Some(Amount::coins(3)));
+        assert_eq!(Amount::checked_sum(&[max, max]), None);
+    }
+}
@@ -0,0 +1,170 @@
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::blockchain::{Chain, Transaction};
+use crate::network::Node;
+
+/// Small HTTP/JSON interface over a shared chain:
+///
+/// - `GET  /blocks/<height>` and `GET /blocks/hash/<hash>`
+/// - `GET  /transactions/<id>`, where the id is the transaction hash
+/// - `GET  /balances/<address>`, with the sequence number its next transfer
+///   has to be signed with, `null` when none is left
+/// - `GET  /pending`
+/// - `POST /transactions` with a signed `Transaction` body
+/// - `POST /mine`
+#[derive(Clone)]
+pub struct Api {
+    chain: Arc<Mutex<Chain>>,
+    node: Option<Node>,
+}
+
+impl Api {
+    pub fn new(chain: Arc<Mutex<Chain>>, node: Option<Node>) -> Api {
+        Api { chain, node }
+    }
+
+    /// Binds `addr` and answers requests on a background thread.
+    pub fn serve(self, addr: &str) -> io::Result<String> {
+        let server = Server::http(addr).map_err(io::Error::other)?;
+        let bound = server.server_addr().to_string();
+        thread::spawn(move || {
+            for request in server.incoming_requests() {
+                self.respond(request);
+            }
+        });
+        Ok(bound)
+    }
+
+    fn respond(&self, mut request: Request) {
+        let mut body = String::new();
+        let (status, payload) = match request.as_reader().read_to_string(&mut body) {
+            Ok(_) => {
+                let path = request.url().split('?').next().unwrap_or("").to_string();
+                self.route(request.method(), &path, &body)
+            },
+            Err(e) => (400, error(&e.to_string())),
+        };
+
+        let content_type = Header::from_bytes("Content-Type", "application/json").unwrap();
+        let response = Response::from_string(payload.to_string())
+            .with_status_code(status)
+            .with_header(content_type);
+        if let Err(e) = request.respond(response) {
+            println!("Failed to answer API request: {}", e);
+        }
+    }
+
+    fn route(&self, method: &Method, path: &str, body: &str) -> (u16, Value) {
+        let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+        match (method, segments.as_slice()) {
+            (Method::Get, ["blocks", "hash", hash]) => {
+                match self.chain.lock().unwrap().block_by_hash(hash) {
+                    Some((height, block)) => (200, json!({ "height": height, "block": block })),
+                    None => (404, error("block not found")),
+                }
+            },
+            (Method::Get, ["blocks", height]) => {
+                let height = match height.parse::<usize>() {
+                    Ok(height) => height,
+                    Err(_) => return (400, error("height must be a number")),
+                };
+                match self.chain.lock().unwrap().block(height) {
+                    Some(block) => (200, json!({ "height": height, "block": block })),
+                    None => (404, error("block not found")),
+                }
+            },
+            (Method::Get, ["transactions", id]) => self.transaction(id),
+            (Method::Get, ["balances", address]) => {
+                let chain = self.chain.lock().unwrap();
+                let (balance, next_sequence) = (chain.balance(address), chain.next_sequence(address));
+                (200, json!({ "address": address, "balance": balance, "display": balance.to_string(), "next_sequence": next_sequence }))
+            },
+            (Method::Get, ["pending"]) => (200, json!(self.chain.lock().unwrap().pending())),
+            (Method::Post, ["transactions"]) => self.submit(body),
+            (Method::Post, ["mine"]) => self.mine(),
+            _ => (404, error("no such endpoint")),
+        }
+    }
+
+    fn transaction(&self, id: &str) -> (u16, Value) {
+        let chain = self.chain.lock().unwrap();
+        if let Some((height, position)) = chain.find_transaction(id) {
+            let trans = &chain.blocks()[height].transactions()[position];
+            return (200, json!({ "id": id, "height": height, "position": position, "transaction": trans }));
+        }
+        match chain.pending().iter().find(|t| Chain::hash(*t) == id) {
+            Some(trans) => (200, json!({ "id": id, "height": null, "transaction": trans })),
+            None => (404, error("transaction not found")),
+        }
+    }
+
+    fn submit(&self, body: &str) -> (u16, Value) {
+        let trans: Transaction = match serde_json::from_str(body) {
+            Ok(trans) => trans,
+            Err(e) => return (400, error(&e.to_string())),
+        };
+        if !self.chain.lock().unwrap().add_transaction(trans.clone()) {
+            return (422, error("transaction rejected"));
+        }
+        if let Some(node) = &self.node {
+            node.broadcast_transaction(&trans);
+        }
+        (201, json!({ "id": Chain::hash(&trans) }))
+    }
+
+    fn mine(&self) -> (u16, Value) {
+        let mut chain = self.chain.lock().unwrap();
+        if !chain.generate_new_block() {
+            return (500, error("block generation failed"));
+        }
+        let height = chain.blocks().len() - 1;
+        let block = chain.blocks()[height].clone();
+        drop(chain);
+
+        if let Some(node) = &self.node {
+            node.broadcast_block(&block);
+        }
+        (201, json!({ "height": height, "block": block }))
+    }
+}
+
+fn error(message: &str) -> Value {
+    json!({ "error": message })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_routes() {
+        let api = Api::new(Arc::new(Mutex::new(Chain::new("miner".to_string(), 1))), None);
+
+        let (status, mined) = api.route(&Method::Post, "/mine", "");
+        assert_eq!(status, 201);
+        assert_eq!(mined["height"], 1);
+
+        let hash = api.chain.lock().unwrap().last_hash();
+        let (status, block) = api.route(&Method::Get, &format!("/blocks/hash/{}", hash), "");
+        assert_eq!((status, block["height"].clone()), (200, json!(1)));
+        assert_eq!(api.route(&Method::Get, "/blocks/1", "").1, block);
+        assert_eq!(api.route(&Method::Get, "/blocks/5", "").0, 404);
+        assert_eq!(api.route(&Method::Get, "/blocks/abc", "").0, 400);
+
+        let (_, balance) = api.route(&Method::Get, "/balances/miner", "");
+        assert_eq!(balance["balance"], 20_000_000_000u64);
+        assert_eq!(balance["display"], "200");
+        assert_eq!(balance["next_sequence"], 1);
+
+        let unsigned = r#"{"sender":"miner","receiver":"bob","amount":100000000}"#;
+        assert_eq!(api.route(&Method::Post, "/transactions", unsigned).0, 422);
+        assert_eq!(api.route(&Method::Post, "/transactions", "{").0, 400);
+        assert_eq!(api.route(&Method::Get, "/pending", "").1, json!([]));
+        assert_eq!(api.route(&Method::Delete, "/pending", "").0, 404);
+    }
+}
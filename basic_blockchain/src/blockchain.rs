@@ -4,96 +4,615 @@ extern crate serde_derive;
 extern crate serde_json;
 extern crate sha2;
 
-use sha2::{Sha256, Digest};
 use chrono::Utc;
-use std::fmt::Write;
-use serde::{Serialize, Deserialize};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use thiserror::Error;
 
-#[derive(Debug, Clone, Serialize)]
-struct Transaction {
+use crate::amount::Amount;
+use crate::difficulty::{RetargetPolicy, MAX_FUTURE_DRIFT};
+use crate::encoding::{self, Encode, BLOCK_VERSION, CANONICAL_VERSION, LEGACY_VERSION, SEQUENCED_VERSION};
+use crate::mempool::{Mempool, DEFAULT_MAX_BLOCK_TRANSACTIONS};
+use crate::merkle::{self, MerkleProof};
+use crate::miner::{CancelHandle, Miner};
+use crate::storage::{Storage, StorageError};
+use crate::wallet;
+
+const REWARD_SENDER: &str = "Root";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Transaction {
     sender: String,
     receiver: String,
-    amount: f32,
+    amount: Amount,
+    #[serde(default, skip_serializing_if = "Amount::is_zero")]
+    fee: Amount,
+    /// Per-sender number that may be used once, see `Chain::next_sequence`.
+    /// Zero for rewards and for transfers in blocks before `SEQUENCED_VERSION`.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    sequence: u64,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    signature: String,
+}
+
+fn is_zero(n: &u64) -> bool {
+    *n == 0
+}
+
+/// Signed body of a transaction in legacy blocks.
+#[derive(Serialize)]
+struct LegacyTransactionBody<'a> {
+    sender: &'a str,
+    receiver: &'a str,
+    amount: Amount,
+    #[serde(skip_serializing_if = "Amount::is_zero")]
+    fee: Amount,
+}
+
+impl Transaction {
+    pub fn fee(&self) -> Amount {
+        self.fee
+    }
+
+    /// Amount plus fee, what the sender is debited.
+    fn total(&self) -> Option<Amount> {
+        self.amount.checked_add(self.fee)
+    }
+
+    fn is_signed(&self, version: u32) -> bool {
+        let body = match version {
+            LEGACY_VERSION => serde_json::to_vec(&LegacyTransactionBody {
+                sender: &self.sender,
+                receiver: &self.receiver,
+                amount: self.amount,
+                fee: self.fee,
+            })
+            .unwrap(),
+            CANONICAL_VERSION => Chain::unsequenced_payload(&self.sender, &self.receiver, self.amount, self.fee),
+            _ => Chain::signing_payload(&self.sender, &self.receiver, self.amount, self.fee, self.sequence),
+        };
+        wallet::verify(&self.sender, &body, &self.signature)
+    }
+
+    /// Merkle leaf of this transaction in a block of the given version.
+    fn leaf_hash(&self, version: u32) -> String {
+        match version {
+            LEGACY_VERSION => encoding::legacy_hash(self),
+            _ => Chain::hash(self),
+        }
+    }
+}
+
+/// The sequence number goes last and only when set, so unsequenced
+/// transactions of older blocks keep their hashes.
+impl Encode for Transaction {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.sender.encode(buf);
+        self.receiver.encode(buf);
+        self.amount.encode(buf);
+        self.fee.encode(buf);
+        self.signature.encode(buf);
+        if self.sequence != 0 {
+            self.sequence.encode(buf);
+        }
+    }
+}
+
+fn legacy_version() -> u32 {
+    LEGACY_VERSION
 }
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Blockheader {
+    #[serde(default = "legacy_version")]
+    version: u32,
     timestamp: i64,
-    nonce: u32,
+    nonce: u64,
     pre_hash: String,
     merkle: String,
     difficulty: u32,
+    /// Reward, size limit and retarget policy the block was mined under, from
+    /// `BLOCK_VERSION` on. Older blocks are checked against `Rules::default()`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    rules: Option<Rules>,
+}
+
+/// Header fields as they were serialized for hashing in legacy blocks.
+#[derive(Serialize)]
+struct LegacyHeader<'a> {
+    timestamp: i64,
+    nonce: u64,
+    pre_hash: &'a str,
+    merkle: &'a str,
+    difficulty: u32,
 }
 
-#[derive(Serialize, Debug)]
+impl Encode for Blockheader {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.version.encode(buf);
+        self.timestamp.encode(buf);
+        self.nonce.encode(buf);
+        self.pre_hash.encode(buf);
+        self.merkle.encode(buf);
+        self.difficulty.encode(buf);
+        if let Some(rules) = &self.rules {
+            rules.encode(buf);
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Block {
     header: Blockheader,
     count: u32,
     transactions: Vec<Transaction>,
 }
 
+impl Blockheader {
+    pub fn new(pre_hash: String, difficulty: u32) -> Blockheader {
+        Blockheader {
+            version: BLOCK_VERSION,
+            timestamp: Utc::now().timestamp(),
+            nonce: 0,
+            pre_hash,
+            merkle: String::new(),
+            difficulty,
+            rules: Some(Rules::default()),
+        }
+    }
+
+    pub fn set_nonce(&mut self, nonce: u64) {
+        self.nonce = nonce;
+    }
+
+    /// Block hash, computed with the hashing rules of the header's version.
+    pub fn hash(&self) -> String {
+        match self.version {
+            LEGACY_VERSION => encoding::legacy_hash(&LegacyHeader {
+                timestamp: self.timestamp,
+                nonce: self.nonce,
+                pre_hash: &self.pre_hash,
+                merkle: &self.merkle,
+                difficulty: self.difficulty,
+            }),
+            _ => Chain::hash(self),
+        }
+    }
+
+    pub fn timestamp(&self) -> i64 {
+        self.timestamp
+    }
+
+    pub fn difficulty(&self) -> u32 {
+        self.difficulty
+    }
+
+    pub fn merkle(&self) -> &str {
+        &self.merkle
+    }
+
+    pub fn pre_hash(&self) -> &str {
+        &self.pre_hash
+    }
+
+    fn rules(&self) -> Rules {
+        self.rules.unwrap_or_default()
+    }
+}
+
+impl Block {
+    pub fn header(&self) -> &Blockheader {
+        &self.header
+    }
+
+    pub fn transactions(&self) -> &[Transaction] {
+        &self.transactions
+    }
+
+    /// Checks that need no chain state: the merkle root covers the
+    /// transactions and the hash meets the header's own difficulty.
+    pub fn is_well_formed(&self) -> bool {
+        self.header.merkle == Chain::get_merkle(self.header.version, &self.transactions)
+            && Chain::meets_difficulty(&self.header.hash(), self.header.difficulty)
+    }
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum ValidationError {
+    #[error("block {index}: pre_hash {found} does not match previous hash {expected}")]
+    BrokenLink { index: usize, expected: String, found: String },
+    #[error("block {index}: count {count} does not match {actual} transactions")]
+    CountMismatch { index: usize, count: u32, actual: usize },
+    #[error("block {index}: merkle root {found} does not match computed {expected}")]
+    MerkleMismatch { index: usize, expected: String, found: String },
+    #[error("block {index}: hash {hash} does not meet difficulty {difficulty}")]
+    InsufficientWork { index: usize, hash: String, difficulty: u32 },
+    #[error("block {index}: first transaction is not a reward transaction")]
+    MissingReward { index: usize },
+    #[error("block {index}: reward transaction at position {position} is not first")]
+    MisplacedReward { index: usize, position: usize },
+    #[error("block {index}: reward {found} does not match the reward of {expected} its rules allow")]
+    WrongReward { index: usize, expected: Amount, found: Amount },
+    #[error("block {index}: transaction amounts overflow")]
+    Overflow { index: usize },
+    #[error("block {index}: unsupported block version {version}")]
+    UnknownVersion { index: usize, version: u32 },
+    #[error("block {index}: transaction at position {position} has an invalid signature")]
+    BadSignature { index: usize, position: usize },
+    #[error("block {index}: difficulty {found} does not match required difficulty {expected}")]
+    WrongDifficulty { index: usize, expected: u32, found: u32 },
+    #[error("block {index}: {count} transactions exceed the limit of {max}")]
+    TooManyTransactions { index: usize, count: usize, max: usize },
+    #[error("block {index}: transaction at position {position} has a missing or already used sequence number")]
+    BadSequence { index: usize, position: usize },
+    #[error("block {index}: transaction at position {position} spends more than its sender holds")]
+    Overdraft { index: usize, position: usize },
+    #[error("block {index}: timestamp {found} is before its parent's {parent} or too far in the future")]
+    BadTimestamp { index: usize, parent: i64, found: i64 },
+    #[error("block {index}: rules are missing or not allowed in block version {version}")]
+    RulesMismatch { index: usize, version: u32 },
+}
+
+impl ValidationError {
+    pub fn index(&self) -> usize {
+        match *self {
+            ValidationError::BrokenLink { index, .. }
+            | ValidationError::CountMismatch { index, .. }
+            | ValidationError::MerkleMismatch { index, .. }
+            | ValidationError::InsufficientWork { index, .. }
+            | ValidationError::MissingReward { index }
+            | ValidationError::MisplacedReward { index, .. }
+            | ValidationError::WrongReward { index, .. }
+            | ValidationError::Overflow { index }
+            | ValidationError::UnknownVersion { index, .. }
+            | ValidationError::BadSignature { index, .. }
+            | ValidationError::WrongDifficulty { index, .. }
+            | ValidationError::TooManyTransactions { index, .. }
+            | ValidationError::BadSequence { index, .. }
+            | ValidationError::Overdraft { index, .. }
+            | ValidationError::BadTimestamp { index, .. }
+            | ValidationError::RulesMismatch { index, .. } => index,
+        }
+    }
+}
+
+/// Settings a block is mined and checked under. Recorded in the header, so
+/// changing them only affects blocks mined afterwards.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+struct Rules {
+    reward: Amount,
+    max_block_transactions: usize,
+    /// Policy the block's difficulty was derived with, `None` when it was
+    /// set by hand.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    retarget: Option<RetargetPolicy>,
+}
+
+impl Encode for Rules {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.reward.encode(buf);
+        self.max_block_transactions.encode(buf);
+        if let Some(policy) = &self.retarget {
+            policy.encode(buf);
+        }
+    }
+}
+
+impl Default for Rules {
+    fn default() -> Rules {
+        Rules {
+            reward: Amount::coins(100),
+            max_block_transactions: DEFAULT_MAX_BLOCK_TRANSACTIONS,
+            retarget: None,
+        }
+    }
+}
+
 pub struct Chain {
     chain: Vec<Block>,
-    curr_trans: Vec<Transaction>,
+    curr_trans: Mempool,
+    /// Rules the next mined block is recorded with.
+    rules: Rules,
     difficulty: u32,
     miner_addr: String,
-    reward: f32,
+    miner: Miner,
+    storage: Option<Storage>,
 }
 
 impl Chain {
+    #[cfg(test)]
     pub fn new(miner_addr: String, difficulty: u32) -> Chain {
         let mut chain = Chain {
             chain: Vec::new(),
-            curr_trans: Vec::new(),
+            curr_trans: Mempool::new(),
+            rules: Rules::default(),
             difficulty,
             miner_addr,
-            reward: 100.0,
+            miner: Miner::default(),
+            storage: None,
         };
 
         chain.generate_new_block();
         chain
     }
 
-    pub fn new_transaction(&mut self, sender: String, receiver: String, amount: f32) -> bool {
-        self.curr_trans.push(Transaction {
+    /// Reloads the chain persisted at `path`, or starts a new one there with
+    /// a fresh genesis block. Every stored block is re-checked on the way in.
+    pub fn open<P: AsRef<Path>>(path: P, miner_addr: String, difficulty: u32) -> Result<Chain, StorageError> {
+        let mut chain = Chain::load(path)?;
+        chain.miner_addr = miner_addr;
+        chain.difficulty = difficulty;
+
+        if chain.chain.is_empty() && !chain.generate_new_block() {
+            return Err(StorageError::Genesis);
+        }
+        Ok(chain)
+    }
+
+    /// Reads and re-checks the chain persisted at `path` without mining
+    /// anything, for read-only queries. A missing file loads as empty.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Chain, StorageError> {
+        let storage = Storage::new(path);
+        let blocks = storage.load()?;
+
+        for (index, block) in blocks.iter().enumerate() {
+            let pre_hash = match index {
+                0 => Chain::genesis_hash(),
+                _ => blocks[index - 1].header.hash(),
+            };
+            Chain::verify_block(index, block, &pre_hash)?;
+            Chain::verify_difficulty(&blocks[..index], block)?;
+        }
+        Ledger::replay(&blocks)?;
+
+        Ok(Chain {
+            difficulty: blocks.last().map_or(1, |block| block.header.difficulty),
+            rules: blocks.last().map_or_else(Rules::default, |block| block.header.rules()),
+            chain: blocks,
+            curr_trans: Mempool::new(),
+            miner_addr: String::new(),
+            miner: Miner::default(),
+            storage: Some(storage),
+        })
+    }
+
+    /// Queues a transfer, refusing zero amounts, spends from the reward sender,
+    /// missing or wrong signatures, any sequence number but the sender's
+    /// `next_sequence` and anything that would overdraw the sender's balance once the fee is
+    /// paid.
+    pub fn new_transaction(&mut self, sender: String, receiver: String, amount: Amount, fee: Amount, sequence: u64, signature: String) -> bool {
+        self.add_transaction(Transaction {
             sender,
             receiver,
             amount,
-        });
+            fee,
+            sequence,
+            signature,
+        })
+    }
+
+    /// Queues an already built transaction, such as one gossiped by a peer,
+    /// under the same rules as `new_transaction`. Duplicates and replays of
+    /// mined transfers are refused by the sequence number.
+    pub fn add_transaction(&mut self, trans: Transaction) -> bool {
+        if trans.amount.is_zero() || trans.sender == REWARD_SENDER {
+            return false;
+        }
+        if self.next_sequence(&trans.sender) != Some(trans.sequence) {
+            return false;
+        }
+        match trans.total() {
+            Some(total) if trans.is_signed(BLOCK_VERSION) && self.balance(&trans.sender) >= total => {},
+            _ => return false,
+        }
+        self.curr_trans.push(trans);
         true
     }
 
+    /// The bytes a wallet signs to authorise a transfer: the canonical
+    /// encoding of every transaction field except the signature.
+    pub fn signing_payload(sender: &str, receiver: &str, amount: Amount, fee: Amount, sequence: u64) -> Vec<u8> {
+        let mut buf = Chain::unsequenced_payload(sender, receiver, amount, fee);
+        sequence.encode(&mut buf);
+        buf
+    }
+
+    /// What transfers in `CANONICAL_VERSION` blocks were signed over.
+    fn unsequenced_payload(sender: &str, receiver: &str, amount: Amount, fee: Amount) -> Vec<u8> {
+        let mut buf = Vec::new();
+        sender.encode(&mut buf);
+        receiver.encode(&mut buf);
+        amount.encode(&mut buf);
+        fee.encode(&mut buf);
+        buf
+    }
+
+    /// Sequence numbers `addr` has used in mined and pending transfers.
+    fn sequences<'a>(&'a self, addr: &'a str) -> impl Iterator<Item = u64> + 'a {
+        self.chain
+            .iter()
+            .flat_map(|block| block.transactions.iter())
+            .chain(self.curr_trans.transactions())
+            .filter(move |t| t.sender == addr && t.sequence != 0)
+            .map(|t| t.sequence)
+    }
+
+    /// Sequence number for the next transfer from `addr`, one past the
+    /// highest it has used. `None` once `u64::MAX` is used, which only a
+    /// block mined elsewhere can do.
+    pub fn next_sequence(&self, addr: &str) -> Option<u64> {
+        self.sequences(addr).max().unwrap_or(0).checked_add(1)
+    }
+
+    /// Balance of `addr` across all mined blocks and the pending transactions.
+    /// A ledger that overdraws or overflows, which only an invalid chain can
+    /// produce, reads as zero.
+    pub fn balance(&self, addr: &str) -> Amount {
+        self.confirmed_balance(addr)
+            .and_then(|confirmed| {
+                self.curr_trans
+                    .transactions()
+                    .iter()
+                    .try_fold(confirmed, |balance, t| Chain::apply(balance, t, addr))
+            })
+            .unwrap_or(Amount::ZERO)
+    }
+
+    fn confirmed_balance(&self, addr: &str) -> Option<Amount> {
+        self.chain
+            .iter()
+            .flat_map(|block| block.transactions.iter())
+            .try_fold(Amount::ZERO, |balance, t| Chain::apply(balance, t, addr))
+    }
+
+    fn apply(mut balance: Amount, t: &Transaction, addr: &str) -> Option<Amount> {
+        if t.receiver == addr {
+            balance = balance.checked_add(t.amount)?;
+        }
+        if t.sender == addr {
+            balance = balance.checked_sub(t.total()?)?;
+        }
+        Some(balance)
+    }
+
+    pub fn block(&self, height: usize) -> Option<&Block> {
+        self.chain.get(height)
+    }
+
+    pub fn blocks(&self) -> &[Block] {
+        &self.chain
+    }
+
+    pub fn block_by_hash(&self, hash: &str) -> Option<(usize, &Block)> {
+        self.chain
+            .iter()
+            .enumerate()
+            .find(|(_, block)| block.header.hash() == hash)
+    }
+
+    /// Height and position of the mined transaction whose hash is `id`.
+    pub fn find_transaction(&self, id: &str) -> Option<(usize, usize)> {
+        self.chain.iter().enumerate().find_map(|(height, block)| {
+            block.transactions
+                .iter()
+                .position(|t| Chain::hash(t) == id)
+                .map(|position| (height, position))
+        })
+    }
+
+    /// Every mined transaction sending to or from `addr`, oldest first, with
+    /// its height and position.
+    pub fn history(&self, addr: &str) -> Vec<(usize, usize, &Transaction)> {
+        self.chain
+            .iter()
+            .enumerate()
+            .flat_map(|(height, block)| {
+                block.transactions
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, t)| t.sender == addr || t.receiver == addr)
+                    .map(move |(position, t)| (height, position, t))
+            })
+            .collect()
+    }
+
+    /// Coins created by block rewards so far. Fees only move existing coins,
+    /// so they are taken back out of the rewards that collected them.
+    pub fn total_supply(&self) -> Option<Amount> {
+        self.chain.iter().flat_map(|block| &block.transactions).try_fold(Amount::ZERO, |supply, t| {
+            match t.sender == REWARD_SENDER {
+                true => supply.checked_add(t.amount),
+                false => supply.checked_sub(t.fee),
+            }
+        })
+    }
+
+    /// Mean seconds between consecutive blocks, if there are at least two.
+    pub fn average_block_time(&self) -> Option<f64> {
+        let (first, last) = (self.chain.first()?, self.chain.last()?);
+        let intervals = self.chain.len() - 1;
+        if intervals == 0 {
+            return None;
+        }
+        Some((last.header.timestamp - first.header.timestamp) as f64 / intervals as f64)
+    }
+
+    pub fn last_block(&self) -> Option<&Block> {
+        self.chain.last()
+    }
+
+    pub fn pending(&self) -> &[Transaction] {
+        self.curr_trans.transactions()
+    }
+
     pub fn last_hash(&self) -> String {
         let block = match self.chain.last() {
             Some(block) => block,
-            None => return String::from_utf8(vec![48; 64]).unwrap(),
+            None => return Chain::genesis_hash(),
         };
-        Chain::hash(&block.header)
+        block.header.hash()
     }
 
+    /// Sets the difficulty for the next block. Refused while a retarget
+    /// policy is in charge of difficulty.
     pub fn update_difficulty(&mut self, difficulty: u32) -> bool {
+        if self.rules.retarget.is_some() {
+            return false;
+        }
         self.difficulty = difficulty;
         true
     }
 
-    pub fn update_reward(&mut self, reward: f32) -> bool {
-        self.reward = reward;
+    /// Changes how difficulty is chosen from the next block on. Blocks
+    /// already in the chain keep being checked against their own policy.
+    pub fn set_retarget(&mut self, policy: Option<RetargetPolicy>) {
+        self.rules.retarget = policy;
+    }
+
+    /// Handle that aborts the block currently being mined by `generate_new_block`.
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.miner.cancel_handle()
+    }
+
+    /// Caps how many pending transactions go into a block, not counting the
+    /// reward transaction, from the next block on.
+    pub fn set_max_block_transactions(&mut self, max: usize) {
+        self.rules.max_block_transactions = max;
+    }
+
+    /// Changes the reward from the next block on. Blocks already in the
+    /// chain keep being checked against the reward they were mined with.
+    pub fn update_reward(&mut self, reward: Amount) -> bool {
+        self.rules.reward = reward;
         true
     }
 
     pub fn generate_new_block(&mut self) -> bool {
-        let header = Blockheader {
-            timestamp: Utc::now().timestamp(),
-            nonce: 0,
-            pre_hash: self.last_hash(),
-            merkle: String::new(),
-            difficulty: self.difficulty,
+        if let (Some(policy), false) = (self.rules.retarget, self.chain.is_empty()) {
+            self.difficulty = policy.next_difficulty(&self.chain);
+        }
+
+        let mut header = Blockheader::new(self.last_hash(), self.difficulty);
+        header.rules = Some(self.rules);
+        if let Some(parent) = self.chain.last() {
+            header.timestamp = header.timestamp.max(parent.header.timestamp);
+        }
+        let selected = self.select_transactions();
+        let reward = match Chain::block_reward(self.rules.reward, &selected) {
+            Some(reward) => reward,
+            None => {
+                println!("Block reward overflows");
+                return false;
+            }
         };
 
         let reward_trans = Transaction {
-            sender: String::from("Root"),
+            sender: String::from(REWARD_SENDER),
             receiver: self.miner_addr.clone(),
-            amount: self.reward,
+            amount: reward,
+            fee: Amount::ZERO,
+            sequence: 0,
+            signature: String::new(),
         };
 
         let mut block = Block {
@@ -103,67 +622,639 @@ impl Chain {
         };
 
         block.transactions.push(reward_trans);
-        block.transactions.append(&mut self.curr_trans);
+        block.transactions.extend(selected);
         block.count = block.transactions.len() as u32;
-        block.header.merkle = Chain::get_merkle(block.transactions.clone());
-        Chain::proof_of_work(&mut block.header);
+        block.header.merkle = Chain::get_merkle(block.header.version, &block.transactions);
+        if !self.proof_of_work(&mut block.header) {
+            return false;
+        }
 
         println!("{:#?}", &block);
-        self.chain.push(block);
+        if let Err(e) = self.commit_block(block) {
+            println!("Failed to persist block: {}", e);
+            return false;
+        }
         true
     }
 
-    fn get_merkle(curr_trans: Vec<Transaction>) -> String {
-        let mut merkle = Vec::new();
+    /// Picks up to `max_block_transactions` pending transactions, highest fee
+    /// first. A transaction is skipped, and stays pending, when its sender
+    /// cannot cover it from confirmed funds plus what the block already holds.
+    fn select_transactions(&self) -> Vec<Transaction> {
+        let mut selected: Vec<Transaction> = Vec::new();
+        for trans in self.curr_trans.by_fee() {
+            if selected.len() >= self.rules.max_block_transactions {
+                break;
+            }
+            let available = self.confirmed_balance(&trans.sender).and_then(|confirmed| {
+                selected
+                    .iter()
+                    .try_fold(confirmed, |balance, t| Chain::apply(balance, t, &trans.sender))
+            });
+            if let (Some(available), Some(total)) = (available, trans.total()) {
+                if available >= total {
+                    selected.push(trans.clone());
+                }
+            }
+        }
+        selected
+    }
+
+    /// Configured reward plus the fees of `transactions`.
+    fn block_reward(reward: Amount, transactions: &[Transaction]) -> Option<Amount> {
+        Amount::checked_sum(transactions.iter().map(|t| &t.fee))?.checked_add(reward)
+    }
+
+    /// Appends a block mined elsewhere if it extends the current tip and
+    /// passes validation. Its transactions leave the pending pool.
+    pub fn append_block(&mut self, block: Block) -> Result<(), StorageError> {
+        let index = self.chain.len();
+        Chain::verify_block(index, &block, &self.last_hash())?;
+        Chain::verify_difficulty(&self.chain, &block)?;
+        Ledger::replay(&self.chain)?.apply(index, &block)?;
+        self.commit_block(block)
+    }
 
-        for t in &curr_trans {
-            let hash = Chain::hash(t);
-            merkle.push(hash);
+    /// Longest valid chain rule: adopts `blocks` when it is longer than the
+    /// current chain and validates from genesis. Pending transactions that
+    /// are not in the new chain and are still affordable stay queued.
+    pub fn replace_chain(&mut self, blocks: Vec<Block>) -> Result<bool, StorageError> {
+        if blocks.len() <= self.chain.len() {
+            return Ok(false);
         }
+        self.validate_blocks(&blocks)?;
+        if let Some(storage) = &self.storage {
+            storage.rewrite(&blocks)?;
+        }
+        self.chain = blocks;
 
-        if merkle.len() % 2 == 1 {
-            let last = merkle.last().cloned().unwrap();
-            merkle.push(last);
+        let included: Vec<String> = self.chain
+            .iter()
+            .flat_map(|block| block.transactions.iter())
+            .map(Chain::hash)
+            .collect();
+        let mut pending = self.curr_trans.take();
+        pending.sort_by_key(|t| t.sequence);
+        for trans in pending {
+            if !included.contains(&Chain::hash(&trans)) {
+                self.add_transaction(trans);
+            }
         }
+        Ok(true)
+    }
 
-        while merkle.len() > 1 {
-            let mut h1 = merkle.remove(0);
-            let mut h2 = merkle.remove(0);
-            h1.push_str(&mut h2);
-            let nh = Chain::hash(&h1);
-            merkle.push(nh);
+    fn commit_block(&mut self, block: Block) -> Result<(), StorageError> {
+        if let Some(storage) = &self.storage {
+            storage.append(&block)?;
         }
-        merkle.pop().unwrap()
+        self.curr_trans.remove_included(&block.transactions);
+        self.chain.push(block);
+        Ok(())
     }
 
-    pub fn proof_of_work(header: &mut Blockheader) {
-        loop {
-            let hash = Chain::hash(header);
-            let slice = &hash[..header.difficulty as usize];
-            if slice.chars().all(|c| c == '0') {
-                println!("Block hash: {}", hash);
-                break;
-            } else {
-                header.nonce += 1;
+    fn genesis_hash() -> String {
+        String::from_utf8(vec![48; 64]).unwrap()
+    }
+
+    /// Walks the whole chain and checks linkage, merkle roots, proof of work
+    /// and the reward transaction of every block, stopping at the first error.
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        self.validate_blocks(&self.chain)
+    }
+
+    fn validate_blocks(&self, blocks: &[Block]) -> Result<(), ValidationError> {
+        let mut pre_hash = Chain::genesis_hash();
+        let mut ledger = Ledger::default();
+        for (index, block) in blocks.iter().enumerate() {
+            Chain::verify_block(index, block, &pre_hash)?;
+            Chain::verify_difficulty(&blocks[..index], block)?;
+            ledger.apply(index, block)?;
+            pre_hash = block.header.hash();
+        }
+        Ok(())
+    }
+
+    fn verify_block(index: usize, block: &Block, pre_hash: &str) -> Result<(), ValidationError> {
+        let version = block.header.version;
+        if !(LEGACY_VERSION..=BLOCK_VERSION).contains(&version) {
+            return Err(ValidationError::UnknownVersion { index, version });
+        }
+        if block.header.rules.is_some() != (version >= BLOCK_VERSION) {
+            return Err(ValidationError::RulesMismatch { index, version });
+        }
+        if block.header.pre_hash != pre_hash {
+            return Err(ValidationError::BrokenLink {
+                index,
+                expected: pre_hash.to_string(),
+                found: block.header.pre_hash.clone(),
+            });
+        }
+        if block.transactions.is_empty() || block.count as usize != block.transactions.len() {
+            return Err(ValidationError::CountMismatch {
+                index,
+                count: block.count,
+                actual: block.transactions.len(),
+            });
+        }
+        let merkle = Chain::get_merkle(version, &block.transactions);
+        if block.header.merkle != merkle {
+            return Err(ValidationError::MerkleMismatch {
+                index,
+                expected: merkle,
+                found: block.header.merkle.clone(),
+            });
+        }
+        if let Some(position) = block.transactions.iter().position(|t| t.sender != REWARD_SENDER && !t.is_signed(version)) {
+            return Err(ValidationError::BadSignature { index, position });
+        }
+        let hash = block.header.hash();
+        if !Chain::meets_difficulty(&hash, block.header.difficulty) {
+            return Err(ValidationError::InsufficientWork {
+                index,
+                hash,
+                difficulty: block.header.difficulty,
+            });
+        }
+        Chain::verify_reward(index, block)?;
+        Chain::verify_size(index, block)
+    }
+
+    fn verify_reward(index: usize, block: &Block) -> Result<(), ValidationError> {
+        let reward = &block.transactions[0];
+        if reward.sender != REWARD_SENDER {
+            return Err(ValidationError::MissingReward { index });
+        }
+        let expected = Chain::block_reward(block.header.rules().reward, &block.transactions[1..])
+            .ok_or(ValidationError::Overflow { index })?;
+        if reward.amount != expected {
+            return Err(ValidationError::WrongReward {
+                index,
+                expected,
+                found: reward.amount,
+            });
+        }
+        if let Some(position) = block.transactions.iter().skip(1).position(|t| t.sender == REWARD_SENDER) {
+            return Err(ValidationError::MisplacedReward { index, position: position + 1 });
+        }
+        Ok(())
+    }
+
+    fn verify_size(index: usize, block: &Block) -> Result<(), ValidationError> {
+        let count = block.transactions.len() - 1;
+        let max = block.header.rules().max_block_transactions;
+        if count > max {
+            return Err(ValidationError::TooManyTransactions { index, count, max });
+        }
+        Ok(())
+    }
+
+    /// Under the retarget policy recorded in the block checks the difficulty,
+    /// and the timestamp it is derived from: not before the parent's, and at
+    /// most `MAX_FUTURE_DRIFT` ahead of the local clock.
+    fn verify_difficulty(previous: &[Block], block: &Block) -> Result<(), ValidationError> {
+        let (policy, parent) = match (block.header.rules().retarget, previous.last()) {
+            (Some(policy), Some(parent)) => (policy, parent.header.timestamp),
+            _ => return Ok(()),
+        };
+        let found = block.header.timestamp;
+        if found < parent || found > Utc::now().timestamp() + MAX_FUTURE_DRIFT {
+            return Err(ValidationError::BadTimestamp {
+                index: previous.len(),
+                parent,
+                found,
+            });
+        }
+        let expected = policy.next_difficulty(previous);
+        if block.header.difficulty != expected {
+            return Err(ValidationError::WrongDifficulty {
+                index: previous.len(),
+                expected,
+                found: block.header.difficulty,
+            });
+        }
+        Ok(())
+    }
+
+    pub fn meets_difficulty(hash: &str, difficulty: u32) -> bool {
+        let difficulty = difficulty as usize;
+        hash.len() >= difficulty && hash[..difficulty].chars().all(|c| c == '0')
+    }
+
+    fn get_merkle(version: u32, transactions: &[Transaction]) -> String {
+        merkle::root(version, transactions.iter().map(|t| t.leaf_hash(version)).collect())
+    }
+
+    /// Inclusion proof for transaction `position` of the block at `height`,
+    /// checkable with `merkle::verify` against that block's header.
+    pub fn merkle_proof(&self, height: usize, position: usize) -> Option<MerkleProof> {
+        let block = self.chain.get(height)?;
+        let version = block.header.version;
+        merkle::proof(version, block.transactions.iter().map(|t| t.leaf_hash(version)).collect(), position)
+    }
+
+    pub fn proof_of_work(&self, header: &mut Blockheader) -> bool {
+        match self.miner.mine(header) {
+            Some(report) => {
+                println!("Block hash: {}", report.hash);
+                println!("Hash rate: {:.0} H/s ({} hashes in {:.2?})", report.hash_rate(), report.hashes, report.elapsed);
+                true
+            },
+            None => {
+                println!("Mining cancelled");
+                false
             }
         }
     }
 
-    pub fn hash<T: serde::Serialize>(item: &T) -> String {
-        let input = serde_json::to_string(&item).unwrap();
-        let mut hasher = Sha256::new();
-        hasher.update(input.as_bytes());
-        let res = hasher.finalize();
-        let vec_res = res.to_vec();
+    /// SHA-256 over the canonical encoding of `item`, as 64 hex characters.
+    /// Also used as the id of a transaction.
+    pub fn hash<T: Encode + ?Sized>(item: &T) -> String {
+        encoding::sha256_hex(&item.to_bytes())
+    }
+}
+
+/// State carried from block to block while validating: every balance and
+/// the sequence numbers each sender has used.
+#[derive(Default)]
+struct Ledger {
+    balances: HashMap<String, Amount>,
+    sequences: HashSet<(String, u64)>,
+}
+
+impl Ledger {
+    /// Ledger after `blocks`, which are checked on the way.
+    fn replay(blocks: &[Block]) -> Result<Ledger, ValidationError> {
+        let mut ledger = Ledger::default();
+        for (index, block) in blocks.iter().enumerate() {
+            ledger.apply(index, block)?;
+        }
+        Ok(ledger)
+    }
+
+    /// Checks the transactions of the block at `index` in order and records
+    /// them. Senders must cover amount and fee from what they hold at that
+    /// point. Sequenced blocks need a fresh sequence number on every
+    /// transfer, older ones none, as their signatures don't cover it.
+    fn apply(&mut self, index: usize, block: &Block) -> Result<(), ValidationError> {
+        let sequenced = block.header.version >= SEQUENCED_VERSION;
+        for (position, t) in block.transactions.iter().enumerate() {
+            if position > 0 {
+                let fresh = match sequenced {
+                    true => t.sequence != 0 && self.sequences.insert((t.sender.clone(), t.sequence)),
+                    false => t.sequence == 0,
+                };
+                if !fresh {
+                    return Err(ValidationError::BadSequence { index, position });
+                }
+            }
+
+            if t.sender != REWARD_SENDER {
+                let balance = self.balance(&t.sender);
+                let balance = Chain::apply(balance, t, &t.sender).ok_or(ValidationError::Overdraft { index, position })?;
+                self.balances.insert(t.sender.clone(), balance);
+            }
+            if t.receiver != t.sender {
+                let balance = self.balance(&t.receiver);
+                let balance = Chain::apply(balance, t, &t.receiver).ok_or(ValidationError::Overflow { index })?;
+                self.balances.insert(t.receiver.clone(), balance);
+            }
+        }
+        Ok(())
+    }
+
+    fn balance(&self, addr: &str) -> Amount {
+        self.balances.get(addr).copied().unwrap_or(Amount::ZERO)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::wallet::Wallet;
+
+    pub(crate) fn coins(amount: &str) -> Amount {
+        amount.parse().unwrap()
+    }
+
+    pub(crate) fn transfer(chain: &mut Chain, from: &Wallet, to: &str, amount: &str) -> bool {
+        transfer_with_fee(chain, from, to, amount, "0")
+    }
+
+    fn transfer_with_fee(chain: &mut Chain, from: &Wallet, to: &str, amount: &str, fee: &str) -> bool {
+        let (amount, fee) = (coins(amount), coins(fee));
+        let sequence = chain.next_sequence(&from.address()).unwrap();
+        let signature = from.sign(&Chain::signing_payload(&from.address(), to, amount, fee, sequence));
+        chain.new_transaction(from.address(), to.to_string(), amount, fee, sequence, signature)
+    }
+
+    pub(crate) fn mined_chain() -> (Chain, Wallet, Wallet) {
+        let miner = Wallet::generate();
+        let bob = Wallet::generate();
+        let mut chain = Chain::new(miner.address(), 1);
+        assert!(transfer(&mut chain, &miner, &bob.address(), "5"));
+        chain.generate_new_block();
+        (chain, miner, bob)
+    }
+
+    #[test]
+    fn test_mempool_prefers_high_fees_and_caps_blocks() {
+        let (mut chain, miner, bob) = mined_chain();
+        chain.set_max_block_transactions(2);
+        assert!(!transfer_with_fee(&mut chain, &bob, "alice", "4", "2"));
+
+        assert!(transfer_with_fee(&mut chain, &miner, "carol", "1", "0.5"));
+        assert!(transfer_with_fee(&mut chain, &miner, "dave", "1", "3"));
+        assert!(transfer(&mut chain, &miner, "erin", "1"));
+        assert!(chain.generate_new_block());
+
+        let block = &chain.chain[2];
+        assert_eq!(block.transactions.len(), 3);
+        assert_eq!(block.transactions[0].amount, coins("103.5"));
+        assert_eq!(block.transactions[1].receiver, "dave");
+        assert_eq!(block.transactions[2].receiver, "carol");
+        assert_eq!(chain.pending().len(), 1);
+        assert_eq!(chain.pending()[0].receiver, "erin");
+        assert_eq!(chain.validate(), Ok(()));
+
+        chain.set_max_block_transactions(1);
+        assert_eq!(chain.validate(), Ok(()));
+        assert!(transfer(&mut chain, &miner, "frank", "1"));
+        assert!(chain.generate_new_block());
+        assert_eq!(chain.chain[3].transactions.len(), 2);
+        assert_eq!(chain.validate(), Ok(()));
+
+        let mut block = chain.chain.pop().unwrap();
+        block.transactions.push(chain.pending()[0].clone());
+        block.transactions[0].amount = Chain::block_reward(coins("100"), &block.transactions[1..]).unwrap();
+        block.count = block.transactions.len() as u32;
+        block.header.merkle = Chain::get_merkle(BLOCK_VERSION, &block.transactions);
+        Miner::new(1).mine(&mut block.header).unwrap();
+        assert!(matches!(
+            chain.append_block(block),
+            Err(StorageError::Invalid(ValidationError::TooManyTransactions { index: 3, count: 2, max: 1 }))
+        ));
+    }
+
+    #[test]
+    fn test_legacy_blocks_still_validate() {
+        let mut chain = Chain::new("miner".to_string(), 1);
+        let genesis = &mut chain.chain[0];
+        genesis.header.version = LEGACY_VERSION;
+        genesis.header.rules = None;
+        genesis.header.merkle = Chain::get_merkle(LEGACY_VERSION, &genesis.transactions);
+        Miner::new(1).mine(&mut genesis.header).unwrap();
+        assert_ne!(genesis.header.hash(), Chain::hash(&genesis.header));
+
+        let json = serde_json::to_string(&chain.chain[0]).unwrap().replace("\"version\":1,", "");
+        chain.chain[0] = serde_json::from_str(&json).unwrap();
+        assert_eq!(chain.chain[0].header.version, LEGACY_VERSION);
+
+        assert!(chain.generate_new_block());
+        assert_eq!(chain.chain[1].header.version, BLOCK_VERSION);
+        assert_eq!(chain.chain[1].header.pre_hash, chain.chain[0].header.hash());
+        assert_eq!(chain.last_hash().len(), 64);
+        assert_eq!(chain.validate(), Ok(()));
+        assert!(merkle::verify(&chain.merkle_proof(0, 0).unwrap(), chain.chain[0].header.merkle()));
+
+        chain.chain[1].header.version = 7;
+        assert_eq!(chain.validate(), Err(ValidationError::UnknownVersion { index: 1, version: 7 }));
+    }
+
+    #[test]
+    fn test_history_and_supply() {
+        let (mut chain, miner, bob) = mined_chain();
+        assert!(transfer_with_fee(&mut chain, &bob, "carol", "1", "0.5"));
+        chain.generate_new_block();
+
+        let history: Vec<(usize, usize)> = chain.history(&bob.address()).iter().map(|(h, p, _)| (*h, *p)).collect();
+        assert_eq!(history, vec![(1, 1), (2, 1)]);
+        assert_eq!(chain.history(&miner.address()).len(), 4);
+        assert_eq!(chain.total_supply(), Some(coins("300")));
+        assert!(chain.average_block_time().is_some());
+        assert_eq!(Chain::new("miner".to_string(), 1).average_block_time(), None);
+    }
+
+    #[test]
+    fn test_find_transaction() {
+        let (chain, ..) = mined_chain();
+        let id = Chain::hash(&chain.chain[1].transactions[1]);
+        assert_eq!(chain.find_transaction(&id), Some((1, 1)));
+        assert_eq!(chain.find_transaction("missing"), None);
+    }
+
+    #[test]
+    fn test_balances_and_overdrafts() {
+        let (mut chain, miner, bob) = mined_chain();
+        assert_eq!(chain.balance(&miner.address()), coins("195"));
+        assert_eq!(chain.balance(&bob.address()), coins("5"));
 
-        Chain::hex_to_string(vec_res.as_slice())
+        assert!(!transfer(&mut chain, &bob, "alice", "6"));
+        assert!(!transfer(&mut chain, &bob, "alice", "0"));
+        assert!(!chain.new_transaction("Root".to_string(), "alice".to_string(), coins("1"), Amount::ZERO, 1, String::new()));
+
+        assert!(transfer(&mut chain, &bob, "alice", "3"));
+        assert!(!transfer(&mut chain, &bob, "alice", "3"));
+        assert_eq!(chain.balance("alice"), coins("3"));
     }
 
-    pub fn hex_to_string(vec_res: &[u8]) -> String {
-        let mut s = String::new();
-        for b in vec_res {
-            write!(&mut s, "{:x}", b).expect("unable to write");
+    #[test]
+    fn test_signatures_are_enforced() {
+        let (mut chain, miner, bob) = mined_chain();
+        let forged = bob.sign(&Chain::signing_payload(&miner.address(), &bob.address(), coins("10"), Amount::ZERO, 2));
+        assert!(!chain.new_transaction(miner.address(), bob.address(), coins("10"), Amount::ZERO, 2, forged));
+        assert!(!chain.new_transaction(miner.address(), bob.address(), coins("10"), Amount::ZERO, 2, String::new()));
+
+        chain.chain[1].transactions[1].signature = miner.sign(b"something else");
+        chain.chain[1].header.merkle = Chain::get_merkle(BLOCK_VERSION, &chain.chain[1].transactions);
+        assert_eq!(
+            chain.validate(),
+            Err(ValidationError::BadSignature { index: 1, position: 1 })
+        );
+    }
+
+    #[test]
+    fn test_mined_transfers_cannot_be_replayed() {
+        let (mut chain, miner, bob) = mined_chain();
+        let mined = chain.chain[1].transactions[1].clone();
+        assert!(!chain.add_transaction(mined.clone()));
+        assert_eq!(chain.next_sequence(&miner.address()), Some(2));
+
+        let mut resigned = mined.clone();
+        resigned.sequence = 0;
+        resigned.signature = miner.sign(&Chain::unsequenced_payload(&miner.address(), &bob.address(), coins("5"), Amount::ZERO));
+        assert!(!chain.add_transaction(resigned));
+
+        chain.curr_trans.push(mined);
+        assert!(chain.generate_new_block());
+        assert_eq!(chain.validate(), Err(ValidationError::BadSequence { index: 2, position: 1 }));
+        let replay = chain.chain.pop().unwrap();
+        assert!(matches!(chain.append_block(replay), Err(StorageError::Invalid(ValidationError::BadSequence { .. }))));
+    }
+
+    #[test]
+    fn test_sequences_must_come_in_order() {
+        let (mut chain, miner, _) = mined_chain();
+        let sign = |sequence| miner.sign(&Chain::signing_payload(&miner.address(), "carol", coins("1"), Amount::ZERO, sequence));
+        for sequence in [0, 3, u64::MAX] {
+            assert!(!chain.new_transaction(miner.address(), "carol".to_string(), coins("1"), Amount::ZERO, sequence, sign(sequence)));
         }
-        s
+        assert_eq!(chain.next_sequence(&miner.address()), Some(2));
+
+        // Blocks only need fresh numbers, so a peer can still mine the last one.
+        assert!(chain.generate_new_block());
+        let mut block = chain.chain.pop().unwrap();
+        block.transactions.push(Transaction {
+            sender: miner.address(),
+            receiver: "carol".to_string(),
+            amount: coins("1"),
+            fee: Amount::ZERO,
+            sequence: u64::MAX,
+            signature: sign(u64::MAX),
+        });
+        block.count = block.transactions.len() as u32;
+        block.header.merkle = Chain::get_merkle(BLOCK_VERSION, &block.transactions);
+        Miner::new(1).mine(&mut block.header).unwrap();
+        chain.append_block(block).unwrap();
+
+        assert_eq!(chain.next_sequence(&miner.address()), None);
+        assert!(!chain.new_transaction(miner.address(), "carol".to_string(), coins("1"), Amount::ZERO, u64::MAX, sign(u64::MAX)));
+        assert_eq!(chain.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_overdrafts_are_rejected_in_blocks() {
+        let (mut chain, ..) = mined_chain();
+        let thief = Wallet::generate();
+        let amount = coins("1000000");
+        let signature = thief.sign(&Chain::signing_payload(&thief.address(), "accomplice", amount, Amount::ZERO, 1));
+        assert!(!chain.new_transaction(thief.address(), "accomplice".to_string(), amount, Amount::ZERO, 1, signature.clone()));
+
+        assert!(chain.generate_new_block());
+        let mut block = chain.chain.pop().unwrap();
+        block.transactions.push(Transaction {
+            sender: thief.address(),
+            receiver: "accomplice".to_string(),
+            amount,
+            fee: Amount::ZERO,
+            sequence: 1,
+            signature,
+        });
+        block.count = block.transactions.len() as u32;
+        block.header.merkle = Chain::get_merkle(BLOCK_VERSION, &block.transactions);
+        Miner::new(1).mine(&mut block.header).unwrap();
+
+        let overdraft = ValidationError::Overdraft { index: 2, position: 1 };
+        assert!(matches!(chain.append_block(block.clone()), Err(StorageError::Invalid(ref e)) if *e == overdraft));
+        assert_eq!(chain.balance("accomplice"), Amount::ZERO);
+        chain.chain.push(block);
+        assert_eq!(chain.validate(), Err(overdraft));
+    }
+
+    #[test]
+    fn test_validate_accepts_mined_chain() {
+        assert_eq!(mined_chain().0.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_difficulty_retargets_on_fast_blocks() {
+        let mut chain = Chain::new("miner".to_string(), 1);
+        chain.set_retarget(Some(RetargetPolicy::new(600, 2)));
+        assert!(!chain.update_difficulty(3));
+
+        chain.generate_new_block();
+        chain.generate_new_block();
+        assert_eq!(chain.chain[1].header.difficulty, 1);
+        assert_eq!(chain.chain[2].header.difficulty, 2);
+        assert_eq!(chain.validate(), Ok(()));
+
+        chain.chain[2].header.difficulty = 1;
+        Miner::new(1).mine(&mut chain.chain[2].header).unwrap();
+        assert_eq!(
+            chain.validate(),
+            Err(ValidationError::WrongDifficulty { index: 2, expected: 2, found: 1 })
+        );
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_retarget_rejects_bad_timestamps() {
+        let mut chain = Chain::new("miner".to_string(), 1);
+        chain.set_retarget(Some(RetargetPolicy::new(600, 2)));
+        chain.generate_new_block();
+        let parent = chain.chain[1].header.timestamp;
+        chain.chain[1].header.timestamp = Utc::now().timestamp() + 60;
+        Miner::new(1).mine(&mut chain.chain[1].header).unwrap();
+        assert!(chain.generate_new_block());
+        assert!(chain.chain[2].header.timestamp >= chain.chain[1].header.timestamp);
+        assert_eq!(chain.validate(), Ok(()));
+
+        chain.chain.truncate(2);
+        let found = parent - 3600;
+        chain.chain[1].header.timestamp = found;
+        Miner::new(1).mine(&mut chain.chain[1].header).unwrap();
+        assert_eq!(
+            chain.validate(),
+            Err(ValidationError::BadTimestamp { index: 1, parent: chain.chain[0].header.timestamp, found })
+        );
+
+        let found = Utc::now().timestamp() + MAX_FUTURE_DRIFT + 60;
+        chain.chain[1].header.timestamp = found;
+        Miner::new(1).mine(&mut chain.chain[1].header).unwrap();
+        assert!(matches!(chain.validate(), Err(ValidationError::BadTimestamp { index: 1, .. })));
+
+        // Blocks are held to the policy they were mined under, not the current one.
+        chain.set_retarget(None);
+        assert!(matches!(chain.validate(), Err(ValidationError::BadTimestamp { index: 1, .. })));
+    }
+
+    #[test]
+    fn test_merkle_proof_for_mined_transaction() {
+        let (chain, ..) = mined_chain();
+        let proof = chain.merkle_proof(1, 1).unwrap();
+        assert_eq!(proof.leaf, Chain::hash(&chain.chain[1].transactions[1]));
+        assert!(merkle::verify(&proof, chain.chain[1].header.merkle()));
+        assert!(!merkle::verify(&proof, chain.chain[0].header.merkle()));
+        assert!(chain.merkle_proof(1, 2).is_none());
+        assert!(chain.merkle_proof(2, 0).is_none());
+    }
+
+    #[test]
+    fn test_validate_reports_failing_block() {
+        let (mut chain, ..) = mined_chain();
+        chain.chain[1].header.pre_hash = Chain::genesis_hash();
+        let err = chain.validate().unwrap_err();
+        assert!(matches!(err, ValidationError::BrokenLink { index: 1, .. }));
+
+        let (mut chain, ..) = mined_chain();
+        chain.chain[1].transactions[1].amount = coins("50");
+        assert!(matches!(chain.validate(), Err(ValidationError::MerkleMismatch { index: 1, .. })));
+    }
+
+    #[test]
+    fn test_reward_changes_apply_from_the_next_block() {
+        let (mut chain, ..) = mined_chain();
+        chain.update_reward(coins("10"));
+        assert_eq!(chain.validate(), Ok(()));
+        assert!(chain.generate_new_block());
+        assert_eq!(chain.chain[2].transactions[0].amount, coins("10"));
+        assert_eq!(chain.validate(), Ok(()));
+
+        let mut peer = Chain::new("peer".to_string(), 1);
+        assert!(peer.replace_chain(chain.chain.clone()).unwrap());
+        assert_eq!(peer.validate(), Ok(()));
+
+        chain.update_reward(coins("20"));
+        chain.update_reward(coins("30"));
+        assert!(chain.generate_new_block());
+        assert_eq!(chain.chain[3].transactions[0].amount, coins("30"));
+        assert_eq!(chain.validate(), Ok(()));
+
+        let block = &mut chain.chain[2];
+        block.transactions[0].amount = coins("100");
+        block.header.merkle = Chain::get_merkle(BLOCK_VERSION, &block.transactions);
+        Miner::new(1).mine(&mut block.header).unwrap();
+        assert_eq!(
+            chain.validate(),
+            Err(ValidationError::WrongReward { index: 2, expected: coins("10"), found: coins("100") })
+        );
+    }
+}
@@ -0,0 +1,62 @@
+use crate::blockchain::Block;
+use crate::encoding::Encode;
+
+const MIN_DIFFICULTY: u32 = 1;
+const MAX_DIFFICULTY: u32 = 64;
+
+/// How far ahead of the local clock a block's timestamp may be, in seconds,
+/// while a retarget policy is active.
+pub const MAX_FUTURE_DRIFT: i64 = 2 * 60 * 60;
+
+/// Adjusts difficulty every `window` blocks so that blocks arrive roughly
+/// every `target_block_time` seconds. Recorded in the header of every block
+/// mined under it, and each block's difficulty is checked against its own.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct RetargetPolicy {
+    target_block_time: i64,
+    window: usize,
+}
+
+impl RetargetPolicy {
+    pub fn new(target_block_time: i64, window: usize) -> RetargetPolicy {
+        RetargetPolicy {
+            target_block_time,
+            window: window.max(2),
+        }
+    }
+
+    /// Difficulty the block following `previous` has to be mined at. Blocks
+    /// keep their parent's difficulty except at the start of each window,
+    /// where the time spent on the last window moves it up or down by one.
+    pub fn next_difficulty(&self, previous: &[Block]) -> u32 {
+        let last = match previous.last() {
+            Some(block) => block.header(),
+            None => return MIN_DIFFICULTY,
+        };
+        // Headers from peers can carry any policy, so don't trust `new`'s bounds.
+        let window = self.window.max(2);
+        let height = previous.len();
+        if height < window || !height.is_multiple_of(window) {
+            return last.difficulty();
+        }
+
+        let first = previous[height - window].header();
+        let elapsed = last.timestamp().saturating_sub(first.timestamp());
+        let expected = self.target_block_time.saturating_mul(window as i64 - 1);
+
+        if elapsed < expected / 2 {
+            (last.difficulty() + 1).min(MAX_DIFFICULTY)
+        } else if elapsed > expected * 2 {
+            last.difficulty().saturating_sub(1).max(MIN_DIFFICULTY)
+        } else {
+            last.difficulty()
+        }
+    }
+}
+
+impl Encode for RetargetPolicy {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.target_block_time.encode(buf);
+        self.window.encode(buf);
+    }
+}
@@ -0,0 +1,136 @@
+use serde_json::{json, Value};
+use thiserror::Error;
+
+use crate::blockchain::Chain;
+use crate::storage::StorageError;
+use crate::CHAIN_PATH;
+
+pub const USAGE: &str = "Usage: basic_blockchain [--chain <file>] <command>
+
+Commands:
+    show-block <height|hash>    Print a block
+    tx <id>                     Print a mined transaction by hash
+    history <address>           Print every transaction to or from an address
+    stats                       Print chain height, total supply and average block time
+    export --json               Print the whole chain as JSON
+
+Without a command the interactive menu is started.";
+
+#[derive(Error, Debug)]
+pub enum CommandError {
+    #[error("{0}\n\n{USAGE}")]
+    Usage(String),
+    #[error("{0} not found")]
+    NotFound(&'static str),
+    #[error("Failed to load chain: {0}")]
+    Storage(#[from] StorageError),
+}
+
+impl CommandError {
+    /// Process exit status: 2 for bad usage, 1 for everything else.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            CommandError::Usage(_) => 2,
+            _ => 1,
+        }
+    }
+}
+
+/// Runs one explorer subcommand against the chain file and prints the
+/// result as JSON to stdout. Nothing is mined or written.
+pub fn run(args: &[String]) -> Result<(), CommandError> {
+    let mut path = CHAIN_PATH;
+    let mut command = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--chain" => match args.next() {
+                Some(file) => path = file,
+                None => return Err(CommandError::Usage("--chain needs a file".to_string())),
+            },
+            arg => command.push(arg),
+        }
+    }
+
+    let chain = Chain::load(path)?;
+    let output = query(&chain, &command)?;
+    println!("{}", serde_json::to_string_pretty(&output).unwrap());
+    Ok(())
+}
+
+fn query(chain: &Chain, command: &[&str]) -> Result<Value, CommandError> {
+    match command {
+        ["show-block", id] => {
+            let found = match id.parse::<usize>() {
+                Ok(height) => chain.block(height).map(|block| (height, block)),
+                Err(_) => chain.block_by_hash(id),
+            };
+            let (height, block) = found.ok_or(CommandError::NotFound("block"))?;
+            Ok(json!({ "height": height, "hash": block.header().hash(), "block": block }))
+        },
+        ["tx", id] => {
+            let (height, position) = chain.find_transaction(id).ok_or(CommandError::NotFound("transaction"))?;
+            let trans = &chain.blocks()[height].transactions()[position];
+            Ok(json!({ "id": id, "height": height, "position": position, "transaction": trans }))
+        },
+        ["history", address] => {
+            let transactions: Vec<Value> = chain
+                .history(address)
+                .into_iter()
+                .map(|(height, position, trans)| {
+                    json!({ "id": Chain::hash(trans), "height": height, "position": position, "transaction": trans })
+                })
+                .collect();
+            let balance = chain.balance(address);
+            Ok(json!({
+                "address": address,
+                "balance": balance,
+                "display": balance.to_string(),
+                "transactions": transactions,
+            }))
+        },
+        ["stats"] => {
+            let supply = chain.total_supply().ok_or(CommandError::NotFound("total supply"))?;
+            Ok(json!({
+                "height": chain.blocks().len().checked_sub(1),
+                "total_supply": supply,
+                "display": supply.to_string(),
+                "average_block_time": chain.average_block_time(),
+            }))
+        },
+        ["export", "--json"] => Ok(json!(chain.blocks())),
+        ["export", ..] => Err(CommandError::Usage("export only supports --json".to_string())),
+        [] => Err(CommandError::Usage("missing command".to_string())),
+        [command, ..] => Err(CommandError::Usage(format!("unknown command or arguments for '{}'", command))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::tests::mined_chain;
+
+    #[test]
+    fn test_queries() {
+        let (chain, _, bob) = mined_chain();
+        let hash = chain.last_hash();
+
+        let by_height = query(&chain, &["show-block", "1"]).unwrap();
+        assert_eq!(by_height["hash"], json!(hash));
+        assert_eq!(query(&chain, &["show-block", &hash]).unwrap(), by_height);
+        assert!(matches!(query(&chain, &["show-block", "7"]), Err(CommandError::NotFound("block"))));
+
+        let history = query(&chain, &["history", &bob.address()]).unwrap();
+        assert_eq!(history["display"], "5");
+        let id = history["transactions"][0]["id"].as_str().unwrap();
+        assert_eq!(query(&chain, &["tx", id]).unwrap()["position"], 1);
+
+        let stats = query(&chain, &["stats"]).unwrap();
+        assert_eq!((stats["height"].clone(), stats["display"].clone()), (json!(1), json!("200")));
+        assert_eq!(query(&chain, &["export", "--json"]).unwrap().as_array().unwrap().len(), 2);
+
+        for bad in [&["export"][..], &["tx"], &["stats", "now"], &[]] {
+            assert_eq!(query(&chain, bad).unwrap_err().exit_code(), 2);
+        }
+    }
+}
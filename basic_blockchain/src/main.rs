@@ -1,15 +1,49 @@
 #[macro_use]
 extern crate serde_derive;
 
+use std::env;
 use std::io;
 use std::process;
 use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use signal_hook::consts::SIGINT;
 
+use amount::Amount;
+
+mod amount;
+mod api;
 mod blockchain;
+mod difficulty;
+mod encoding;
+mod explorer;
+mod mempool;
+mod merkle;
+mod miner;
+mod network;
+mod storage;
+mod wallet;
+
+const CHAIN_PATH: &str = "chain.jsonl";
+const RETARGET_WINDOW: usize = 5;
 
 fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if !args.is_empty() {
+        if let Err(e) = explorer::run(&args) {
+            eprintln!("{}", e);
+            process::exit(e.exit_code());
+        }
+        return;
+    }
+
     let mut miner_addr = String::new();
     let mut difficulty = String::new();
+    let mut block_time = String::new();
+    let mut max_block_trans = String::new();
+    let mut chain_path = String::new();
+    let mut listen_addr = String::new();
+    let mut api_addr = String::new();
     let mut choice = String::new();
 
     print!("Input a miner address: ");
@@ -20,9 +54,91 @@ fn main() {
     io::stdout().flush().expect("Failed to flush stdout");
     io::stdin().read_line(&mut difficulty).expect("Failed to read input");
 
+    print!("Target block time in seconds (0 for manual difficulty): ");
+    io::stdout().flush().expect("Failed to flush stdout");
+    io::stdin().read_line(&mut block_time).expect("Failed to read input");
+
+    print!("Max transactions per block [{}]: ", mempool::DEFAULT_MAX_BLOCK_TRANSACTIONS);
+    io::stdout().flush().expect("Failed to flush stdout");
+    io::stdin().read_line(&mut max_block_trans).expect("Failed to read input");
+
+    print!("Chain file [{}]: ", CHAIN_PATH);
+    io::stdout().flush().expect("Failed to flush stdout");
+    io::stdin().read_line(&mut chain_path).expect("Failed to read input");
+
+    print!("Listen address, e.g. 127.0.0.1:7001 (blank to run offline): ");
+    io::stdout().flush().expect("Failed to flush stdout");
+    io::stdin().read_line(&mut listen_addr).expect("Failed to read input");
+
     let diff = difficulty.trim().parse::<u32>().expect("We need an integer value");
-    println!("Generating genesis block!");
-    let mut chain = blockchain::Chain::new(miner_addr.trim().to_string(), diff);
+    let block_time_val = block_time.trim().parse::<i64>().expect("We need an integer value");
+    let max_block_trans_val = match max_block_trans.trim() {
+        "" => mempool::DEFAULT_MAX_BLOCK_TRANSACTIONS,
+        max => max.parse::<usize>().expect("We need an integer value"),
+    };
+    let chain_path = match chain_path.trim() {
+        "" => CHAIN_PATH,
+        path => path,
+    };
+    println!("Loading chain from {}", chain_path);
+    let mut chain = match blockchain::Chain::open(chain_path, miner_addr.trim().to_string(), diff) {
+        Ok(chain) => chain,
+        Err(e) => {
+            println!("Failed to load chain: {}", e);
+            process::exit(1);
+        }
+    };
+    chain.set_retarget((block_time_val > 0).then(|| difficulty::RetargetPolicy::new(block_time_val, RETARGET_WINDOW)));
+    chain.set_max_block_transactions(max_block_trans_val);
+
+    // Ctrl-C cancels a mining run and keeps its default action, terminating
+    // the process, everywhere else. The miner discards cancels issued before
+    // a run starts.
+    let idle = Arc::new(AtomicBool::new(true));
+    signal_hook::flag::register_conditional_default(SIGINT, idle.clone())
+        .expect("Failed to register Ctrl-C handler");
+    signal_hook::flag::register(SIGINT, chain.cancel_handle().flag())
+        .expect("Failed to register Ctrl-C handler");
+    let chain = Arc::new(Mutex::new(chain));
+
+    let node = if listen_addr.trim().is_empty() {
+        None
+    } else {
+        let mut peers = String::new();
+        print!("Peer addresses (comma separated): ");
+        io::stdout().flush().expect("Failed to flush stdout");
+        io::stdin().read_line(&mut peers).expect("Failed to read input");
+
+        let peers = peers
+            .split(',')
+            .map(|p| p.trim().to_string())
+            .filter(|p| !p.is_empty())
+            .collect();
+        let mut node = network::Node::new(chain.clone(), peers);
+        match node.listen(listen_addr.trim()) {
+            Ok(addr) => println!("Listening for peers on {}", addr),
+            Err(e) => {
+                println!("Failed to listen on {}: {}", listen_addr.trim(), e);
+                process::exit(1);
+            }
+        }
+        node.sync();
+        Some(node)
+    };
+
+    print!("HTTP API address, e.g. 127.0.0.1:8000 (blank to disable): ");
+    io::stdout().flush().expect("Failed to flush stdout");
+    io::stdin().read_line(&mut api_addr).expect("Failed to read input");
+
+    if !api_addr.trim().is_empty() {
+        match api::Api::new(chain.clone(), node.clone()).serve(api_addr.trim()) {
+            Ok(addr) => println!("Serving HTTP API on http://{}", addr),
+            Err(e) => {
+                println!("Failed to serve HTTP API on {}: {}", api_addr.trim(), e);
+                process::exit(1);
+            }
+        }
+    }
 
     loop {
         println!("Menu:");
@@ -30,12 +146,17 @@ fn main() {
         println!("2. Mine block");
         println!("3. Change difficulty");
         println!("4. Change reward");
+        println!("5. Validate chain");
+        println!("6. Balance of address");
+        println!("7. Generate wallet");
+        println!("8. Sign and send transfer");
+        println!("9. Merkle proof for transaction");
         println!("0. Exit");
         print!("Enter your choice: ");
         io::stdout().flush().expect("Failed to flush stdout");
         choice.clear();
         io::stdin().read_line(&mut choice).expect("Failed to read input");
-        println!("");
+        println!();
 
         match choice.trim().parse::<u32>() {
             Ok(0) => {
@@ -46,6 +167,9 @@ fn main() {
                 let mut sender = String::new();
                 let mut receiver = String::new();
                 let mut amount = String::new();
+                let mut fee = String::new();
+                let mut sequence = String::new();
+                let mut signature = String::new();
 
                 print!("Enter sender's address: ");
                 io::stdout().flush().expect("Failed to flush stdout");
@@ -59,20 +183,50 @@ fn main() {
                 io::stdout().flush().expect("Failed to flush stdout");
                 io::stdin().read_line(&mut amount).expect("Failed to read input");
 
-                let amount_val = amount.trim().parse::<f32>().expect("Failed to parse amount");
+                print!("Enter fee (blank for none): ");
+                io::stdout().flush().expect("Failed to flush stdout");
+                io::stdin().read_line(&mut fee).expect("Failed to read input");
+
+                print!("Enter sequence number: ");
+                io::stdout().flush().expect("Failed to flush stdout");
+                io::stdin().read_line(&mut sequence).expect("Failed to read input");
 
-                let res = chain.new_transaction(sender.trim().to_string(), receiver.trim().to_string(), amount_val);
+                print!("Enter signature: ");
+                io::stdout().flush().expect("Failed to flush stdout");
+                io::stdin().read_line(&mut signature).expect("Failed to read input");
+
+                let amount_val = amount.trim().parse::<Amount>().expect("Failed to parse amount");
+                let fee_val = parse_fee(&fee);
+                let sequence_val = sequence.trim().parse::<u64>().expect("Failed to parse sequence number");
+
+                let res = chain.lock().unwrap().new_transaction(
+                    sender.trim().to_string(),
+                    receiver.trim().to_string(),
+                    amount_val,
+                    fee_val,
+                    sequence_val,
+                    signature.trim().to_string(),
+                );
                 if res {
                     println!("Transaction added");
+                    broadcast_last_transaction(&chain, &node);
                 } else {
                     println!("Transaction failed");
                 }
             },
             Ok(2) => {
-                println!("Generating Block");
-                let res = chain.generate_new_block();
+                println!("Generating Block, press Ctrl-C to cancel");
+                idle.store(false, Ordering::SeqCst);
+                let res = chain.lock().unwrap().generate_new_block();
+                idle.store(true, Ordering::SeqCst);
                 if res {
                     println!("Block generated successfully");
+                    if let Some(node) = &node {
+                        let tip = chain.lock().unwrap().last_block().cloned();
+                        if let Some(tip) = tip {
+                            node.broadcast_block(&tip);
+                        }
+                    }
                 } else {
                     println!("Block generation failed");
                 }
@@ -84,11 +238,11 @@ fn main() {
                 io::stdin().read_line(&mut new_diff).expect("Failed to read input");
 
                 let new_diff_val = new_diff.trim().parse::<u32>().expect("Failed to parse new difficulty");
-                let res = chain.update_difficulty(new_diff_val);
+                let res = chain.lock().unwrap().update_difficulty(new_diff_val);
                 if res {
                     println!("Updated difficulty");
                 } else {
-                    println!("Failed to update difficulty");
+                    println!("Failed to update difficulty, it is retargeted automatically");
                 }
             },
             Ok(4) => {
@@ -97,17 +251,130 @@ fn main() {
                 io::stdout().flush().expect("Failed to flush stdout");
                 io::stdin().read_line(&mut new_reward).expect("Failed to read input");
 
-                let new_reward_val = new_reward.trim().parse::<f32>().expect("Failed to parse new reward");
-                let res = chain.update_reward(new_reward_val);
+                let new_reward_val = new_reward.trim().parse::<Amount>().expect("Failed to parse new reward");
+                let res = chain.lock().unwrap().update_reward(new_reward_val);
                 if res {
                     println!("Updated reward");
                 } else {
                     println!("Failed to update reward");
                 }
             },
+            Ok(5) => {
+                let res = chain.lock().unwrap().validate();
+                match res {
+                    Ok(()) => println!("Chain is valid"),
+                    Err(e) => println!("Chain is invalid at block {}: {}", e.index(), e),
+                }
+            },
+            Ok(6) => {
+                let mut addr = String::new();
+                print!("Enter address: ");
+                io::stdout().flush().expect("Failed to flush stdout");
+                io::stdin().read_line(&mut addr).expect("Failed to read input");
+
+                println!("Balance: {}", chain.lock().unwrap().balance(addr.trim()));
+            },
+            Ok(7) => {
+                let wallet = wallet::Wallet::generate();
+                println!("Address: {}", wallet.address());
+                println!("Secret key: {}", wallet.secret());
+                println!("Keep the secret key safe, it is needed to sign transfers");
+            },
+            Ok(8) => {
+                let mut secret = String::new();
+                let mut receiver = String::new();
+                let mut amount = String::new();
+                let mut fee = String::new();
+
+                print!("Enter sender's secret key: ");
+                io::stdout().flush().expect("Failed to flush stdout");
+                io::stdin().read_line(&mut secret).expect("Failed to read input");
+
+                print!("Enter receiver's address: ");
+                io::stdout().flush().expect("Failed to flush stdout");
+                io::stdin().read_line(&mut receiver).expect("Failed to read input");
+
+                print!("Enter amount: ");
+                io::stdout().flush().expect("Failed to flush stdout");
+                io::stdin().read_line(&mut amount).expect("Failed to read input");
+
+                print!("Enter fee (blank for none): ");
+                io::stdout().flush().expect("Failed to flush stdout");
+                io::stdin().read_line(&mut fee).expect("Failed to read input");
+
+                let wallet = match wallet::Wallet::from_secret(&secret) {
+                    Ok(wallet) => wallet,
+                    Err(e) => {
+                        println!("{}", e);
+                        continue;
+                    }
+                };
+                let amount_val = amount.trim().parse::<Amount>().expect("Failed to parse amount");
+                let fee_val = parse_fee(&fee);
+                let mut chain_guard = chain.lock().unwrap();
+                let sequence = match chain_guard.next_sequence(&wallet.address()) {
+                    Some(sequence) => sequence,
+                    None => {
+                        println!("This address has used every sequence number");
+                        continue;
+                    }
+                };
+                let signature = wallet.sign(&blockchain::Chain::signing_payload(&wallet.address(), receiver.trim(), amount_val, fee_val, sequence));
+                println!("Sequence number: {}", sequence);
+                println!("Signature: {}", signature);
+
+                let res = chain_guard.new_transaction(wallet.address(), receiver.trim().to_string(), amount_val, fee_val, sequence, signature);
+                drop(chain_guard);
+                if res {
+                    println!("Transaction added");
+                    broadcast_last_transaction(&chain, &node);
+                } else {
+                    println!("Transaction failed");
+                }
+            },
+            Ok(9) => {
+                let mut height = String::new();
+                let mut position = String::new();
+
+                print!("Enter block height: ");
+                io::stdout().flush().expect("Failed to flush stdout");
+                io::stdin().read_line(&mut height).expect("Failed to read input");
+
+                print!("Enter transaction position: ");
+                io::stdout().flush().expect("Failed to flush stdout");
+                io::stdin().read_line(&mut position).expect("Failed to read input");
+
+                let height_val = height.trim().parse::<usize>().expect("Failed to parse height");
+                let position_val = position.trim().parse::<usize>().expect("Failed to parse position");
+
+                let chain = chain.lock().unwrap();
+                match (chain.block(height_val), chain.merkle_proof(height_val, position_val)) {
+                    (Some(block), Some(proof)) => {
+                        println!("{:#?}", proof);
+                        println!("Verified against merkle root: {}", merkle::verify(&proof, block.header().merkle()));
+                    },
+                    _ => println!("No such transaction"),
+                }
+            },
             _ => {
                 println!("Please select a valid option");
             }
         }
     }
 }
+
+fn parse_fee(fee: &str) -> Amount {
+    match fee.trim() {
+        "" => Amount::ZERO,
+        fee => fee.parse::<Amount>().expect("Failed to parse fee"),
+    }
+}
+
+fn broadcast_last_transaction(chain: &Arc<Mutex<blockchain::Chain>>, node: &Option<network::Node>) {
+    if let Some(node) = node {
+        let trans = chain.lock().unwrap().pending().last().cloned();
+        if let Some(trans) = trans {
+            node.broadcast_transaction(&trans);
+        }
+    }
+}
@@ -0,0 +1,41 @@
+use crate::blockchain::{Chain, Transaction};
+
+pub const DEFAULT_MAX_BLOCK_TRANSACTIONS: usize = 100;
+
+/// Transactions waiting to be mined, kept in arrival order.
+#[derive(Default)]
+pub struct Mempool {
+    transactions: Vec<Transaction>,
+}
+
+impl Mempool {
+    pub fn new() -> Mempool {
+        Mempool::default()
+    }
+
+    pub fn push(&mut self, trans: Transaction) {
+        self.transactions.push(trans);
+    }
+
+    pub fn transactions(&self) -> &[Transaction] {
+        &self.transactions
+    }
+
+    /// Pending transactions ordered by fee, highest first. Equal fees keep
+    /// their arrival order.
+    pub fn by_fee(&self) -> Vec<&Transaction> {
+        let mut ordered: Vec<&Transaction> = self.transactions.iter().collect();
+        ordered.sort_by_key(|t| std::cmp::Reverse(t.fee()));
+        ordered
+    }
+
+    /// Drops every pending transaction that appears in `mined`.
+    pub fn remove_included(&mut self, mined: &[Transaction]) {
+        let included: Vec<String> = mined.iter().map(Chain::hash).collect();
+        self.transactions.retain(|t| !included.contains(&Chain::hash(t)));
+    }
+
+    pub fn take(&mut self) -> Vec<Transaction> {
+        std::mem::take(&mut self.transactions)
+    }
+}
@@ -0,0 +1,97 @@
+use std::collections::VecDeque;
+
+use crate::encoding;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ProofStep {
+    pub hash: String,
+    pub side: Side,
+}
+
+/// Sibling hashes from a transaction leaf up to the block's merkle root.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MerkleProof {
+    pub version: u32,
+    pub leaf: String,
+    pub steps: Vec<ProofStep>,
+}
+
+/// Folds `leaves` into a merkle root. The tree is built as a queue: pairs are
+/// taken from the front and their parent pushed to the back, with the last
+/// leaf duplicated once up front when the count is odd.
+pub fn root(version: u32, leaves: Vec<String>) -> String {
+    fold(version, leaves, None).0
+}
+
+/// Builds the inclusion proof for the leaf at `index`.
+pub fn proof(version: u32, leaves: Vec<String>, index: usize) -> Option<MerkleProof> {
+    let leaf = leaves.get(index)?.clone();
+    let (_, steps) = fold(version, leaves, Some(index));
+    Some(MerkleProof { version, leaf, steps })
+}
+
+/// Checks `proof` against a `Blockheader.merkle` root without the block body.
+pub fn verify(proof: &MerkleProof, root: &str) -> bool {
+    let hash = proof.steps.iter().fold(proof.leaf.clone(), |hash, step| match step.side {
+        Side::Left => encoding::hash_pair(proof.version, &step.hash, &hash),
+        Side::Right => encoding::hash_pair(proof.version, &hash, &step.hash),
+    });
+    hash == root
+}
+
+fn fold(version: u32, leaves: Vec<String>, target: Option<usize>) -> (String, Vec<ProofStep>) {
+    let mut queue: VecDeque<(String, bool)> = leaves
+        .into_iter()
+        .enumerate()
+        .map(|(i, hash)| (hash, Some(i) == target))
+        .collect();
+    let mut steps = Vec::new();
+
+    if queue.len() % 2 == 1 {
+        let (last, _) = queue.back().cloned().unwrap();
+        queue.push_back((last, false));
+    }
+
+    while queue.len() > 1 {
+        let (h1, t1) = queue.pop_front().unwrap();
+        let (h2, t2) = queue.pop_front().unwrap();
+        if t1 {
+            steps.push(ProofStep { hash: h2.clone(), side: Side::Right });
+        } else if t2 {
+            steps.push(ProofStep { hash: h1.clone(), side: Side::Left });
+        }
+        queue.push_back((encoding::hash_pair(version, &h1, &h2), t1 || t2));
+    }
+    (queue.pop_front().unwrap().0, steps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoding::{sha256_hex, BLOCK_VERSION, CANONICAL_VERSION, LEGACY_VERSION, SEQUENCED_VERSION};
+
+    #[test]
+    fn test_proofs_verify_against_root() {
+        for version in [LEGACY_VERSION, CANONICAL_VERSION, SEQUENCED_VERSION, BLOCK_VERSION] {
+            for count in 1..8usize {
+                let leaves: Vec<String> = (0..count).map(|i| sha256_hex(&i.to_be_bytes())).collect();
+                let root = root(version, leaves.clone());
+                for index in 0..count {
+                    let proof = proof(version, leaves.clone(), index).unwrap();
+                    assert!(verify(&proof, &root));
+
+                    let mut forged = proof.clone();
+                    forged.leaf = sha256_hex(b"forged");
+                    assert!(!verify(&forged, &root));
+                }
+                assert!(proof(version, leaves, count).is_none());
+            }
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::blockchain::{Blockheader, Chain};
+
+/// Shared flag that stops a mining run from another thread or a signal handler.
+#[derive(Clone, Default)]
+pub struct CancelHandle {
+    flag: Arc<AtomicBool>,
+    parent: Arc<Mutex<Option<String>>>,
+}
+
+impl CancelHandle {
+    pub fn cancel(&self) {
+        self.flag.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.flag.load(Ordering::SeqCst)
+    }
+
+    pub fn flag(&self) -> Arc<AtomicBool> {
+        self.flag.clone()
+    }
+
+    /// Hash of the block the current run extends, `None` while no run is
+    /// in progress.
+    pub fn parent(&self) -> Option<String> {
+        self.parent.lock().unwrap().clone()
+    }
+
+    fn reset(&self) {
+        self.flag.store(false, Ordering::SeqCst);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MiningReport {
+    pub hash: String,
+    pub hashes: u64,
+    pub elapsed: Duration,
+}
+
+impl MiningReport {
+    pub fn hash_rate(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs > 0.0 {
+            self.hashes as f64 / secs
+        } else {
+            self.hashes as f64
+        }
+    }
+}
+
+/// Proof-of-work search split across worker threads. Worker `i` tries the
+/// nonces `i, i + threads, i + 2 * threads, ...` until one of them finds a
+/// hash meeting the header's difficulty or the run is cancelled.
+pub struct Miner {
+    threads: usize,
+    cancel: CancelHandle,
+}
+
+impl Miner {
+    pub fn new(threads: usize) -> Miner {
+        Miner {
+            threads: threads.max(1),
+            cancel: CancelHandle::default(),
+        }
+    }
+
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.cancel.clone()
+    }
+
+    /// Searches for a nonce and writes it into `header`. Returns `None` if the
+    /// run was cancelled first; a cancel issued before the call is discarded.
+    pub fn mine(&self, header: &mut Blockheader) -> Option<MiningReport> {
+        self.cancel.reset();
+        *self.cancel.parent.lock().unwrap() = Some(header.pre_hash().to_string());
+        let found = AtomicBool::new(false);
+        let hashes = AtomicU64::new(0);
+        let result: Mutex<Option<(u64, String)>> = Mutex::new(None);
+        let start = Instant::now();
+
+        thread::scope(|scope| {
+            for worker in 0..self.threads {
+                let mut candidate = header.clone();
+                let (found, hashes, result) = (&found, &hashes, &result);
+                let cancel = &self.cancel;
+                let step = self.threads as u64;
+
+                scope.spawn(move || {
+                    let mut nonce = Some(worker as u64);
+                    let mut count = 0;
+                    while let Some(n) = nonce {
+                        if found.load(Ordering::Relaxed) || cancel.is_cancelled() {
+                            break;
+                        }
+                        candidate.set_nonce(n);
+                        let hash = candidate.hash();
+                        count += 1;
+                        if Chain::meets_difficulty(&hash, candidate.difficulty()) {
+                            if !found.swap(true, Ordering::SeqCst) {
+                                *result.lock().unwrap() = Some((n, hash));
+                            }
+                            break;
+                        }
+                        nonce = n.checked_add(step);
+                    }
+                    hashes.fetch_add(count, Ordering::Relaxed);
+                });
+            }
+        });
+        *self.cancel.parent.lock().unwrap() = None;
+
+        let (nonce, hash) = result.into_inner().unwrap()?;
+        header.set_nonce(nonce);
+        Some(MiningReport {
+            hash,
+            hashes: hashes.into_inner(),
+            elapsed: start.elapsed(),
+        })
+    }
+}
+
+impl Default for Miner {
+    fn default() -> Miner {
+        Miner::new(thread::available_parallelism().map(|n| n.get()).unwrap_or(1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cancelled_miner_returns_none() {
+        let miner = Miner::new(2);
+        let handle = miner.cancel_handle();
+        let mut header = Blockheader::new(Chain::hash("parent"), 64);
+
+        let canceller = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            handle.cancel();
+        });
+        assert!(miner.mine(&mut header).is_none());
+        canceller.join().unwrap();
+    }
+}
@@ -0,0 +1,294 @@
+use std::collections::HashSet;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::blockchain::{Block, Chain, Transaction};
+use crate::miner::CancelHandle;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum Message {
+    Transaction(Transaction),
+    Block(Block),
+    GetChain,
+    Chain(Vec<Block>),
+}
+
+/// Every message is sent as one JSON line tagged with the sender's listen
+/// address, so the receiver knows where to ask for a chain when it forks.
+#[derive(Serialize, Deserialize, Debug)]
+struct Envelope {
+    from: String,
+    message: Message,
+}
+
+/// A chain shared with a TCP listener that gossips transactions and blocks
+/// to a fixed list of peers and follows the longest valid chain.
+#[derive(Clone)]
+pub struct Node {
+    addr: String,
+    peers: Vec<String>,
+    chain: Arc<Mutex<Chain>>,
+    cancel: CancelHandle,
+    seen: Arc<Mutex<HashSet<String>>>,
+}
+
+impl Node {
+    pub fn new(chain: Arc<Mutex<Chain>>, peers: Vec<String>) -> Node {
+        let cancel = chain.lock().unwrap().cancel_handle();
+        Node {
+            addr: String::new(),
+            peers,
+            chain,
+            cancel,
+            seen: Arc::new(Mutex::new(HashSet::new())),
+        }
+    }
+
+    /// Binds `addr` and serves peers on a background thread, one thread per
+    /// connection. Returns the bound address, useful when binding port 0.
+    pub fn listen(&mut self, addr: &str) -> io::Result<String> {
+        let listener = TcpListener::bind(addr)?;
+        self.addr = listener.local_addr()?.to_string();
+
+        let node = self.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        let node = node.clone();
+                        thread::spawn(move || node.serve(stream));
+                    },
+                    Err(e) => println!("Failed to accept peer: {}", e),
+                }
+            }
+        });
+        Ok(self.addr.clone())
+    }
+
+    /// Asks every peer for its chain and adopts the longest valid one.
+    pub fn sync(&self) {
+        for peer in &self.peers {
+            self.sync_from(peer);
+        }
+    }
+
+    pub fn broadcast_transaction(&self, trans: &Transaction) {
+        self.seen.lock().unwrap().insert(Chain::hash(trans));
+        self.gossip(Message::Transaction(trans.clone()), None);
+    }
+
+    pub fn broadcast_block(&self, block: &Block) {
+        self.mark_seen(block);
+        self.gossip(Message::Block(block.clone()), None);
+    }
+
+    fn serve(&self, stream: TcpStream) {
+        let mut reader = BufReader::new(&stream);
+        let mut line = String::new();
+        if reader.read_line(&mut line).is_err() {
+            return;
+        }
+        let envelope: Envelope = match serde_json::from_str(&line) {
+            Ok(envelope) => envelope,
+            Err(e) => {
+                println!("Ignoring malformed peer message: {}", e);
+                return;
+            }
+        };
+
+        match envelope.message {
+            Message::Transaction(trans) => self.receive_transaction(trans, &envelope.from),
+            Message::Block(block) => self.receive_block(block, &envelope.from),
+            Message::GetChain => {
+                let blocks = self.chain.lock().unwrap().blocks().to_vec();
+                if let Err(e) = self.write(&stream, Message::Chain(blocks)) {
+                    println!("Failed to send chain to {}: {}", envelope.from, e);
+                }
+            },
+            Message::Chain(_) => {},
+        }
+    }
+
+    fn receive_transaction(&self, trans: Transaction, from: &str) {
+        if !self.seen.lock().unwrap().insert(Chain::hash(&trans)) {
+            return;
+        }
+        if self.chain.lock().unwrap().add_transaction(trans.clone()) {
+            self.gossip(Message::Transaction(trans), Some(from));
+        }
+    }
+
+    fn receive_block(&self, block: Block, from: &str) {
+        let hash = block.header().hash();
+        if self.seen.lock().unwrap().contains(&hash) || !block.is_well_formed() {
+            return;
+        }
+
+        // A block extending the tip makes whatever we are mining stale, and
+        // mining holds the chain lock, so stop it before trying to take the
+        // lock. Anything else waits for the run to finish.
+        if self.cancel.parent().as_deref() == Some(block.header().pre_hash()) {
+            self.cancel.cancel();
+        }
+        let res = self.chain.lock().unwrap().append_block(block.clone());
+        match res {
+            Ok(()) => {
+                println!("Accepted block {} from {}", hash, from);
+                self.mark_seen(&block);
+                self.gossip(Message::Block(block), Some(from));
+            },
+            Err(_) => self.sync_from(from),
+        }
+    }
+
+    fn sync_from(&self, peer: &str) {
+        let blocks = match self.request_chain(peer) {
+            Ok(blocks) => blocks,
+            Err(e) => {
+                println!("Failed to fetch chain from {}: {}", peer, e);
+                return;
+            }
+        };
+
+        let res = self.chain.lock().unwrap().replace_chain(blocks);
+        match res {
+            Ok(true) => {
+                println!("Switched to longer chain from {}", peer);
+                let tip = self.chain.lock().unwrap().last_block().cloned();
+                if let Some(tip) = tip {
+                    self.mark_seen(&tip);
+                    self.gossip(Message::Block(tip), Some(peer));
+                }
+            },
+            Ok(false) => {},
+            Err(e) => println!("Rejected chain from {}: {}", peer, e),
+        }
+    }
+
+    fn request_chain(&self, peer: &str) -> io::Result<Vec<Block>> {
+        let stream = self.connect(peer)?;
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+        self.write(&stream, Message::GetChain)?;
+
+        let mut line = String::new();
+        BufReader::new(&stream).read_line(&mut line)?;
+        match serde_json::from_str::<Envelope>(&line)?.message {
+            Message::Chain(blocks) => Ok(blocks),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidData, "expected a chain")),
+        }
+    }
+
+    fn gossip(&self, message: Message, skip: Option<&str>) {
+        let line = match self.encode(message) {
+            Ok(line) => line,
+            Err(e) => {
+                println!("Failed to encode message: {}", e);
+                return;
+            }
+        };
+        for peer in self.peers.iter().filter(|p| Some(p.as_str()) != skip) {
+            let res = self.connect(peer).and_then(|mut stream| stream.write_all(line.as_bytes()));
+            if let Err(e) = res {
+                println!("Failed to reach peer {}: {}", peer, e);
+            }
+        }
+    }
+
+    fn mark_seen(&self, block: &Block) {
+        let mut seen = self.seen.lock().unwrap();
+        seen.insert(block.header().hash());
+        seen.extend(block.transactions().iter().map(Chain::hash));
+    }
+
+    fn connect(&self, peer: &str) -> io::Result<TcpStream> {
+        let addr = peer
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "unresolvable peer address"))?;
+        TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT)
+    }
+
+    fn write(&self, mut stream: &TcpStream, message: Message) -> io::Result<()> {
+        stream.write_all(self.encode(message)?.as_bytes())
+    }
+
+    fn encode(&self, message: Message) -> io::Result<String> {
+        let envelope = Envelope {
+            from: self.addr.clone(),
+            message,
+        };
+        let mut line = serde_json::to_string(&envelope)?;
+        line.push('\n');
+        Ok(line)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    fn node(peers: Vec<String>) -> Node {
+        let chain = Arc::new(Mutex::new(Chain::new("miner".to_string(), 1)));
+        let mut node = Node::new(chain, peers);
+        node.listen("127.0.0.1:0").unwrap();
+        node
+    }
+
+    fn wait_for<F: Fn() -> bool>(condition: F) -> bool {
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_secs(5) {
+            if condition() {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        false
+    }
+
+    #[test]
+    fn test_peer_follows_longer_chain() {
+        let a = node(vec![]);
+        let b = node(vec![a.addr.clone()]);
+        let a = Node { peers: vec![b.addr.clone()], ..a };
+
+        assert!(a.chain.lock().unwrap().generate_new_block());
+        let tip = a.chain.lock().unwrap().last_block().cloned().unwrap();
+        a.broadcast_block(&tip);
+
+        let tip_hash = tip.header().hash();
+        assert!(wait_for(|| b.chain.lock().unwrap().last_hash() == tip_hash));
+        assert_eq!(b.chain.lock().unwrap().blocks().len(), 2);
+    }
+
+    #[test]
+    fn test_only_well_formed_blocks_on_the_tip_stop_mining() {
+        let mut other = Chain::new("other".to_string(), 1);
+        assert!(other.generate_new_block());
+        let node = node(vec![]);
+        assert!(node.chain.lock().unwrap().replace_chain(other.blocks().to_vec()).unwrap());
+        assert!(other.generate_new_block());
+        let block = other.last_block().cloned().unwrap();
+
+        node.chain.lock().unwrap().update_difficulty(64);
+        let chain = node.chain.clone();
+        let mining = thread::spawn(move || chain.lock().unwrap().generate_new_block());
+        assert!(wait_for(|| node.cancel.parent().is_some()));
+
+        let mut tampered = serde_json::to_value(&block).unwrap();
+        tampered["header"]["merkle"] = Chain::hash("tampered").into();
+        node.receive_block(serde_json::from_value(tampered).unwrap(), "peer");
+        thread::sleep(Duration::from_millis(100));
+        assert!(!mining.is_finished());
+
+        node.receive_block(block.clone(), "peer");
+        assert!(!mining.join().unwrap());
+        assert_eq!(node.chain.lock().unwrap().last_hash(), block.header().hash());
+    }
+}
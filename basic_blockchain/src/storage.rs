@@ -0,0 +1,178 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use thiserror::Error;
+
+use crate::blockchain::{Block, ValidationError};
+
+#[derive(Error, Debug)]
+pub enum StorageError {
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Corrupt record on line {line}: {source}")]
+    Corrupt {
+        line: usize,
+        source: serde_json::Error,
+    },
+    #[error("Invalid chain: {0}")]
+    Invalid(#[from] ValidationError),
+    #[error("Unable to persist genesis block")]
+    Genesis,
+}
+
+/// Append-only block log, one JSON encoded `Block` per line.
+pub struct Storage {
+    path: PathBuf,
+}
+
+impl Storage {
+    pub fn new<P: AsRef<Path>>(path: P) -> Storage {
+        Storage {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+
+    pub fn load(&self) -> Result<Vec<Block>, StorageError> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut blocks = Vec::new();
+        for (i, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let block = serde_json::from_str(&line)
+                .map_err(|source| StorageError::Corrupt { line: i + 1, source })?;
+            blocks.push(block);
+        }
+        Ok(blocks)
+    }
+
+    /// Replaces the whole log, used when switching to a longer fork. The new
+    /// log is written beside the old one and renamed over it.
+    pub fn rewrite(&self, blocks: &[Block]) -> Result<(), StorageError> {
+        let tmp = self.path.with_extension("tmp");
+        let mut file = File::create(&tmp)?;
+        for block in blocks {
+            let record = serde_json::to_string(block)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            writeln!(file, "{}", record)?;
+        }
+        file.sync_data()?;
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+
+    pub fn append(&self, block: &Block) -> Result<(), StorageError> {
+        let mut record = serde_json::to_string(block)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        record.push('\n');
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        file.write_all(record.as_bytes())?;
+        file.sync_data()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::tests::transfer;
+    use crate::amount::Amount;
+    use crate::blockchain::Chain;
+    use crate::difficulty::RetargetPolicy;
+    use crate::wallet::Wallet;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("basic_blockchain_{}_{}.jsonl", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn test_chain_reloads_from_disk() {
+        let path = temp_path("reload");
+        let miner = Wallet::generate();
+        let mut chain = Chain::open(&path, miner.address(), 1).unwrap();
+        assert!(transfer(&mut chain, &miner, "bob", "5"));
+        assert!(chain.generate_new_block());
+        let last_hash = chain.last_hash();
+
+        let reloaded = Chain::open(&path, "miner".to_string(), 1).unwrap();
+        assert_eq!(reloaded.last_hash(), last_hash);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_rule_changes_survive_a_reload() {
+        let path = temp_path("rules");
+        let miner = Wallet::generate();
+        let mut chain = Chain::open(&path, miner.address(), 1).unwrap();
+        chain.update_reward(Amount::coins(10));
+        chain.set_max_block_transactions(1);
+        assert!(transfer(&mut chain, &miner, "bob", "5"));
+        assert!(chain.generate_new_block());
+
+        let mut reloaded = Chain::open(&path, miner.address(), 1).unwrap();
+        assert_eq!(reloaded.validate(), Ok(()));
+        assert!(reloaded.generate_new_block());
+        assert_eq!(reloaded.balance(&miner.address()), Amount::coins(115));
+        assert_eq!(Chain::open(&path, miner.address(), 1).unwrap().validate(), Ok(()));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_retarget_policy_survives_a_reload() {
+        let path = temp_path("retarget");
+        let miner = Wallet::generate();
+        let mut chain = Chain::open(&path, miner.address(), 1).unwrap();
+        chain.set_retarget(Some(RetargetPolicy::new(600, 2)));
+        assert!(chain.generate_new_block());
+        assert!(chain.generate_new_block());
+        assert_eq!(chain.blocks()[2].header().difficulty(), 2);
+
+        // Reopened with manual difficulty, then with another block time.
+        let mut reloaded = Chain::open(&path, miner.address(), 1).unwrap();
+        reloaded.set_retarget(None);
+        assert!(reloaded.update_difficulty(1));
+        assert!(reloaded.generate_new_block());
+        let mut reloaded = Chain::open(&path, miner.address(), 1).unwrap();
+        reloaded.set_retarget(Some(RetargetPolicy::new(1, 3)));
+        assert!(reloaded.generate_new_block());
+        assert_eq!(reloaded.validate(), Ok(()));
+        assert_eq!(Chain::open(&path, miner.address(), 1).unwrap().validate(), Ok(()));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_tampered_chain_is_rejected() {
+        let path = temp_path("tamper");
+        let miner = Wallet::generate();
+        let mut chain = Chain::open(&path, miner.address(), 1).unwrap();
+        assert!(transfer(&mut chain, &miner, "bob", "5"));
+        assert!(chain.generate_new_block());
+
+        let data = fs::read_to_string(&path).unwrap();
+        fs::write(&path, data.replace("\"amount\":500000000", "\"amount\":50000000000")).unwrap();
+        assert!(matches!(
+            Chain::open(&path, "miner".to_string(), 1),
+            Err(StorageError::Invalid(ValidationError::MerkleMismatch { index: 1, .. }))
+        ));
+
+        fs::write(&path, "not a block\n").unwrap();
+        assert!(matches!(
+            Chain::open(&path, "miner".to_string(), 1),
+            Err(StorageError::Corrupt { line: 1, .. })
+        ));
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,60 @@
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use rand::rngs::OsRng;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum WalletError {
+    #[error("Secret key must be 64 hex characters")]
+    InvalidSecret,
+}
+
+/// An Ed25519 keypair. The address is the hex encoded public key, so anyone
+/// holding a transaction can check its signature without a key registry.
+pub struct Wallet {
+    signing_key: SigningKey,
+}
+
+impl Wallet {
+    pub fn generate() -> Wallet {
+        Wallet {
+            signing_key: SigningKey::generate(&mut OsRng),
+        }
+    }
+
+    pub fn from_secret(secret: &str) -> Result<Wallet, WalletError> {
+        let bytes = hex::decode(secret.trim()).map_err(|_| WalletError::InvalidSecret)?;
+        let seed: [u8; 32] = bytes.try_into().map_err(|_| WalletError::InvalidSecret)?;
+        Ok(Wallet {
+            signing_key: SigningKey::from_bytes(&seed),
+        })
+    }
+
+    pub fn address(&self) -> String {
+        hex::encode(self.signing_key.verifying_key().as_bytes())
+    }
+
+    pub fn secret(&self) -> String {
+        hex::encode(self.signing_key.to_bytes())
+    }
+
+    pub fn sign(&self, message: &[u8]) -> String {
+        hex::encode(self.signing_key.sign(message).to_bytes())
+    }
+}
+
+/// Checks a hex `signature` over `message` against the key encoded in `address`.
+pub fn verify(address: &str, message: &[u8], signature: &str) -> bool {
+    let key = match hex::decode(address).ok().and_then(|b| <[u8; 32]>::try_from(b).ok()) {
+        Some(key) => key,
+        None => return false,
+    };
+    let key = match VerifyingKey::from_bytes(&key) {
+        Ok(key) => key,
+        Err(_) => return false,
+    };
+    let signature = match hex::decode(signature).ok().and_then(|b| <[u8; 64]>::try_from(b).ok()) {
+        Some(signature) => Signature::from_bytes(&signature),
+        None => return false,
+    };
+    key.verify(message, &signature).is_ok()
+}
@@ -0,0 +1,94 @@
+use std::sync::{Arc, Mutex};
+use chrono::Duration;
+use rand_core::{OsRng, RngCore};
+use sha2::{Digest, Sha256};
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use iron::headers::{Authorization, Bearer};
+use iron::typemap::Key;
+use iron::{BeforeMiddleware, IronResult, Request};
+
+use crate::database::Database;
+use crate::error::ApiError;
+
+/// How long a bearer token is accepted after login.
+pub const TOKEN_LIFETIME: Duration = Duration::days(30);
+
+/// Username of the request's bearer token, set by `AuthMiddleware`.
+pub struct CurrentUser;
+
+impl Key for CurrentUser{
+  type Value = String;
+}
+
+pub fn hash_password(password: &str) -> Result<String, argon2::password_hash::Error>{
+  let salt = SaltString::generate(&mut OsRng);
+  Ok(Argon2::default().hash_password(password.as_bytes(), &salt)?.to_string())
+}
+
+pub fn verify_password(password: &str, hash: &str) -> bool{
+  match PasswordHash::new(hash){
+    Ok(hash) => Argon2::default().verify_password(password.as_bytes(), &hash).is_ok(),
+    Err(_) => false
+  }
+}
+
+/// 256 random bits as hex.
+pub fn new_token() -> String{
+  let mut bytes = [0u8; 32];
+  OsRng.fill_bytes(&mut bytes);
+  bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// SHA-256 of a bearer token as hex, the form tokens are stored and looked
+/// up in, so a leaked database holds no usable tokens.
+pub fn hash_token(token: &str) -> String{
+  Sha256::digest(token.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Resolves an `Authorization: Bearer <token>` header to `CurrentUser`.
+/// Requests without the header pass through anonymously, handlers decide
+/// whether they need a user. An unknown or expired token is rejected with 401.
+pub struct AuthMiddleware{
+  database: Arc<Mutex<Box<dyn Database>>>
+}
+
+impl AuthMiddleware{
+  pub fn new(database: Arc<Mutex<Box<dyn Database>>>) -> AuthMiddleware{
+    AuthMiddleware {database}
+  }
+}
+
+impl BeforeMiddleware for AuthMiddleware{
+  fn before(&self, req: &mut Request) -> IronResult<()>{
+    let token = match req.headers.get::<Authorization<Bearer>>(){
+      Some(header) => header.token.clone(),
+      None => return Ok(())
+    };
+    let username = self.database.lock().unwrap().token_user(&hash_token(&token))?;
+    match username{
+      Some(username) => {
+        req.extensions.insert::<CurrentUser>(username);
+        Ok(())
+      },
+      None => Err(ApiError::unauthorized("invalid or expired bearer token".to_string()).into())
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests{
+  use super::*;
+
+  #[test]
+  fn test_passwords_and_tokens(){
+    let hash = hash_password("correct horse").unwrap();
+    assert!(verify_password("correct horse", &hash));
+    assert!(!verify_password("wrong horse", &hash));
+    assert!(!verify_password("correct horse", "not a hash"));
+    assert_eq!(new_token().len(), 64);
+    assert_ne!(new_token(), new_token());
+    assert_eq!(hash_token("token"), hash_token("token"));
+    assert_ne!(hash_token("token"), "token");
+  }
+}
@@ -0,0 +1,187 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use serde::Deserialize;
+
+const DEFAULT_CONFIG_PATH: &str = "config.json";
+const DEFAULT_DATABASE_PATH: &str = "posts.db";
+
+/// Storage backend for posts. In the config file either `"memory"` or
+/// `{"sqlite": "posts.db"}`.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend{
+  Memory,
+  Sqlite(PathBuf)
+}
+
+impl Default for Backend{
+  fn default() -> Backend{
+    Backend::Sqlite(PathBuf::from(DEFAULT_DATABASE_PATH))
+  }
+}
+
+/// Request throttling and size limits, see `limits`.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Limits{
+  pub requests_per_second: f64,
+  pub burst: u32,
+  pub max_body_bytes: u64
+}
+
+impl Default for Limits{
+  fn default() -> Limits{
+    Limits {requests_per_second: 10.0, burst: 20, max_body_bytes: 64 * 1024}
+  }
+}
+
+/// Server settings. Read from the JSON file named by `POSTS_CONFIG`, or
+/// `config.json` when it exists, and then overridden by the environment.
+/// Keys missing from the file keep their defaults.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config{
+  pub bind: String,
+  pub threads: usize,
+  /// `env_logger` filter, `RUST_LOG` still takes precedence.
+  pub log_level: String,
+  pub storage: Backend,
+  /// Adds the sample posts when the storage has none.
+  pub seed: bool,
+  pub limits: Limits
+}
+
+impl Default for Config{
+  fn default() -> Config{
+    let cpus = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    Config{
+      bind: "localhost:8080".to_string(),
+      threads: 8 * cpus,
+      log_level: "info".to_string(),
+      storage: Backend::default(),
+      seed: true,
+      limits: Limits::default()
+    }
+  }
+}
+
+impl Config{
+  pub fn load() -> Result<Config, String>{
+    let mut config = match env::var("POSTS_CONFIG"){
+      Ok(path) => Config::from_file(Path::new(&path))?,
+      Err(_) if Path::new(DEFAULT_CONFIG_PATH).exists() => Config::from_file(Path::new(DEFAULT_CONFIG_PATH))?,
+      Err(_) => Config::default()
+    };
+    config.override_with(|name| env::var(name).ok())?;
+    Ok(config)
+  }
+
+  fn from_file(path: &Path) -> Result<Config, String>{
+    let text = fs::read_to_string(path).map_err(|e| format!("can't read {}: {}", path.display(), e))?;
+    Config::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+  }
+
+  fn parse(text: &str) -> Result<Config, String>{
+    let config: Config = serde_json::from_str(text).map_err(|e| e.to_string())?;
+    config.validate()?;
+    Ok(config)
+  }
+
+  /// Applies the environment variables:
+  ///
+  /// * `POSTS_BIND`, `POSTS_THREADS`, `POSTS_LOG_LEVEL` and `POSTS_SEED`
+  /// * `POSTS_STORAGE`, `sqlite` or `memory`, and `POSTS_DATABASE`, the
+  ///   SQLite file
+  /// * `RATE_LIMIT_PER_SECOND`, `RATE_LIMIT_BURST` and `MAX_BODY_BYTES`
+  fn override_with<F: Fn(&str) -> Option<String>>(&mut self, var: F) -> Result<(), String>{
+    if let Some(bind) = var("POSTS_BIND"){
+      self.bind = bind;
+    }
+    if let Some(threads) = parse_var(&var, "POSTS_THREADS")?{
+      self.threads = threads;
+    }
+    if let Some(level) = var("POSTS_LOG_LEVEL"){
+      self.log_level = level;
+    }
+    if let Some(seed) = parse_var(&var, "POSTS_SEED")?{
+      self.seed = seed;
+    }
+
+    let path = var("POSTS_DATABASE").map(PathBuf::from);
+    match var("POSTS_STORAGE").as_deref(){
+      Some("memory") => self.storage = Backend::Memory,
+      Some("sqlite") => self.storage = Backend::Sqlite(path.unwrap_or_else(|| PathBuf::from(DEFAULT_DATABASE_PATH))),
+      Some(other) => return Err(format!("unknown POSTS_STORAGE '{}', expected sqlite or memory", other)),
+      None => if let (Some(path), Backend::Sqlite(_)) = (path, &self.storage){
+        self.storage = Backend::Sqlite(path);
+      }
+    }
+
+    if let Some(rate) = parse_var(&var, "RATE_LIMIT_PER_SECOND")?{
+      self.limits.requests_per_second = rate;
+    }
+    if let Some(burst) = parse_var(&var, "RATE_LIMIT_BURST")?{
+      self.limits.burst = burst;
+    }
+    if let Some(max) = parse_var(&var, "MAX_BODY_BYTES")?{
+      self.limits.max_body_bytes = max;
+    }
+    self.validate()
+  }
+
+  fn validate(&self) -> Result<(), String>{
+    if self.threads == 0{
+      return Err("threads must be at least 1".to_string());
+    }
+    let rate = self.limits.requests_per_second;
+    if !rate.is_finite() || rate <= 0.0{
+      return Err("requests_per_second must be positive".to_string());
+    }
+    if self.limits.burst == 0{
+      return Err("burst must be at least 1".to_string());
+    }
+    Ok(())
+  }
+}
+
+fn parse_var<T: std::str::FromStr, F: Fn(&str) -> Option<String>>(var: &F, name: &str) -> Result<Option<T>, String>{
+  match var(name){
+    Some(value) => value.trim().parse().map(Some).map_err(|_| format!("invalid {} '{}'", name, value)),
+    None => Ok(None)
+  }
+}
+
+#[cfg(test)]
+mod tests{
+  use super::*;
+
+  #[test]
+  fn test_file_and_overrides(){
+    let mut config = Config::parse(r#"{
+      "bind": "0.0.0.0:80",
+      "storage": {"sqlite": "data/posts.db"},
+      "limits": {"burst": 5}
+    }"#).unwrap();
+    assert_eq!(config.bind, "0.0.0.0:80");
+    assert_eq!(config.storage, Backend::Sqlite(PathBuf::from("data/posts.db")));
+    assert_eq!(config.limits, Limits {burst: 5, ..Limits::default()});
+    assert!(config.seed);
+
+    let env = |name: &str| match name{
+      "POSTS_SEED" => Some("false".to_string()),
+      "POSTS_DATABASE" => Some("other.db".to_string()),
+      "POSTS_THREADS" => Some("4".to_string()),
+      _ => None
+    };
+    config.override_with(env).unwrap();
+    assert_eq!((config.seed, config.threads), (false, 4));
+    assert_eq!(config.storage, Backend::Sqlite(PathBuf::from("other.db")));
+
+    assert!(config.clone().override_with(|name| (name == "POSTS_STORAGE").then(|| "redis".to_string())).is_err());
+    assert!(config.override_with(|name| (name == "POSTS_THREADS").then(|| "0".to_string())).is_err());
+    assert!(Config::parse(r#"{"port": 80}"#).is_err());
+    assert_eq!(Config::parse("{}").unwrap(), Config::default());
+  }
+}
@@ -0,0 +1,153 @@
+use std::collections::HashMap;
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::database::{Database, DatabaseError};
+use crate::feed::{FeedPage, FeedQuery};
+use crate::models::{Comment, Post, User};
+use crate::search::{SearchIndex, SearchPage, SearchQuery};
+
+/// Keeps posts in a `Vec`, everything is lost on restart.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryDatabase{
+  posts: Vec<Post>,
+  comments: Vec<Comment>,
+  users: HashMap<String, User>,
+  /// Username and expiry by token hash.
+  tokens: HashMap<String, (String, DateTime<Utc>)>,
+  index: SearchIndex
+}
+
+impl MemoryDatabase{
+  pub fn new() -> MemoryDatabase{
+    MemoryDatabase::default()
+  }
+
+  fn post_mut(&mut self, id: &Uuid) -> Result<&mut Post, DatabaseError>{
+    self.posts.iter_mut().find(|p| p.uuid() == id).ok_or(DatabaseError::NotFound(*id))
+  }
+}
+
+impl Database for MemoryDatabase{
+  fn add_post(&mut self, post: Post) -> Result<(), DatabaseError>{
+    if self.posts.iter().any(|p| p.uuid() == post.uuid()){
+      return Err(DatabaseError::Duplicate(*post.uuid()));
+    }
+    self.index.insert(&post);
+    self.posts.push(post);
+    Ok(())
+  }
+
+  fn posts(&self) -> Result<Vec<Post>, DatabaseError>{
+    Ok(self.posts.clone())
+  }
+
+  fn count_posts(&self) -> Result<usize, DatabaseError>{
+    Ok(self.posts.len())
+  }
+
+  fn post(&self, id: &Uuid) -> Result<Option<Post>, DatabaseError>{
+    Ok(self.posts.iter().find(|p| p.uuid() == id).cloned())
+  }
+
+  fn feed(&self, query: &FeedQuery) -> Result<FeedPage, DatabaseError>{
+    Ok(query.apply(&self.posts, |post| self.comments.iter().filter(|c| &c.post == post.uuid()).count()))
+  }
+
+  fn update_post(&mut self, post: Post) -> Result<Post, DatabaseError>{
+    let stored = self.post_mut(post.uuid())?;
+    *stored = post.clone();
+    self.index.insert(&post);
+    Ok(post)
+  }
+
+  fn delete_post(&mut self, id: &Uuid) -> Result<Post, DatabaseError>{
+    match self.posts.iter().position(|p| p.uuid() == id){
+      Some(index) => {
+        self.comments.retain(|c| &c.post != id);
+        self.index.remove(id);
+        Ok(self.posts.remove(index))
+      },
+      None => Err(DatabaseError::NotFound(*id))
+    }
+  }
+
+  fn add_comment(&mut self, comment: Comment) -> Result<(), DatabaseError>{
+    if !self.posts.iter().any(|p| p.uuid() == &comment.post){
+      return Err(DatabaseError::NotFound(comment.post));
+    }
+    self.comments.push(comment);
+    Ok(())
+  }
+
+  fn comments(&self, post: &Uuid) -> Result<Vec<Comment>, DatabaseError>{
+    if !self.posts.iter().any(|p| p.uuid() == post){
+      return Err(DatabaseError::NotFound(*post));
+    }
+    Ok(self.comments.iter().filter(|c| &c.post == post).cloned().collect())
+  }
+
+  fn delete_comment(&mut self, post: &Uuid, id: &Uuid) -> Result<Comment, DatabaseError>{
+    match self.comments.iter().position(|c| &c.post == post && &c.uuid == id){
+      Some(index) => Ok(self.comments.remove(index)),
+      None => Err(DatabaseError::CommentNotFound(*id))
+    }
+  }
+
+  fn add_user(&mut self, user: User) -> Result<(), DatabaseError>{
+    if self.users.contains_key(&user.username){
+      return Err(DatabaseError::UserExists(user.username));
+    }
+    self.users.insert(user.username.clone(), user);
+    Ok(())
+  }
+
+  fn user(&self, username: &str) -> Result<Option<User>, DatabaseError>{
+    Ok(self.users.get(username).cloned())
+  }
+
+  fn add_token(&mut self, token_hash: &str, username: &str, expires: DateTime<Utc>) -> Result<(), DatabaseError>{
+    self.tokens.insert(token_hash.to_string(), (username.to_string(), expires));
+    Ok(())
+  }
+
+  fn token_user(&self, token_hash: &str) -> Result<Option<String>, DatabaseError>{
+    let now = Utc::now();
+    Ok(self.tokens.get(token_hash).filter(|(_, expires)| *expires > now).map(|(username, _)| username.clone()))
+  }
+
+  fn search(&self, query: &SearchQuery) -> Result<SearchPage, DatabaseError>{
+    SearchPage::collect(query, self.index.search(&query.text), |id| self.post(id))
+  }
+}
+
+#[cfg(test)]
+mod tests{
+  use super::*;
+  use crate::database::tests::{check_comments, check_crud, check_feed, check_search, check_users};
+
+  #[test]
+  fn test_crud(){
+    check_crud(&mut MemoryDatabase::new());
+  }
+
+  #[test]
+  fn test_feed(){
+    check_feed(&mut MemoryDatabase::new());
+  }
+
+  #[test]
+  fn test_users(){
+    check_users(&mut MemoryDatabase::new());
+  }
+
+  #[test]
+  fn test_comments(){
+    check_comments(&mut MemoryDatabase::new());
+  }
+
+  #[test]
+  fn test_search(){
+    check_search(&mut MemoryDatabase::new());
+  }
+}
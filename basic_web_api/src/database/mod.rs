@@ -0,0 +1,236 @@
+mod memory;
+mod sqlite;
+
+use std::error::Error;
+use std::fmt;
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::config::Backend;
+use crate::feed::{FeedPage, FeedQuery};
+use crate::models::{Comment, Post, PostPatch, User};
+use crate::search::{SearchPage, SearchQuery};
+
+pub use memory::MemoryDatabase;
+pub use sqlite::SqliteDatabase;
+
+#[derive(Debug)]
+pub enum DatabaseError{
+  NotFound(Uuid),
+  Duplicate(Uuid),
+  UserExists(String),
+  CommentNotFound(Uuid),
+  Sqlite(rusqlite::Error)
+}
+
+impl fmt::Display for DatabaseError{
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result{
+    match self{
+      DatabaseError::NotFound(id) => write!(f, "post {} not found", id),
+      DatabaseError::Duplicate(id) => write!(f, "post {} already exists", id),
+      DatabaseError::UserExists(name) => write!(f, "user {} already exists", name),
+      DatabaseError::CommentNotFound(id) => write!(f, "comment {} not found", id),
+      DatabaseError::Sqlite(e) => write!(f, "storage error: {}", e)
+    }
+  }
+}
+
+impl Error for DatabaseError{}
+
+impl From<rusqlite::Error> for DatabaseError{
+  fn from(e: rusqlite::Error) -> DatabaseError{
+    DatabaseError::Sqlite(e)
+  }
+}
+
+/// Post and user storage shared by the handlers and the auth middleware.
+/// Posts come back in the order they were added.
+pub trait Database: Send{
+  fn add_post(&mut self, post: Post) -> Result<(), DatabaseError>;
+
+  fn posts(&self) -> Result<Vec<Post>, DatabaseError>;
+
+  fn count_posts(&self) -> Result<usize, DatabaseError>;
+
+  fn post(&self, id: &Uuid) -> Result<Option<Post>, DatabaseError>;
+
+  /// One filtered and sorted page of posts.
+  fn feed(&self, query: &FeedQuery) -> Result<FeedPage, DatabaseError>;
+
+  /// Replaces the stored post that has the same uuid as `post`.
+  fn update_post(&mut self, post: Post) -> Result<Post, DatabaseError>;
+
+  /// Deletes the post together with its comments.
+  fn delete_post(&mut self, id: &Uuid) -> Result<Post, DatabaseError>;
+
+  /// Changes only the fields set in `patch`.
+  fn patch_post(&mut self, id: &Uuid, patch: PostPatch) -> Result<Post, DatabaseError>{
+    let mut post = self.post(id)?.ok_or(DatabaseError::NotFound(*id))?;
+    post.apply(patch);
+    self.update_post(post)
+  }
+
+  /// Fails with `NotFound` if the comment's post does not exist.
+  fn add_comment(&mut self, comment: Comment) -> Result<(), DatabaseError>;
+
+  /// Comments on a post, oldest first.
+  fn comments(&self, post: &Uuid) -> Result<Vec<Comment>, DatabaseError>;
+
+  fn delete_comment(&mut self, post: &Uuid, id: &Uuid) -> Result<Comment, DatabaseError>;
+
+  fn add_user(&mut self, user: User) -> Result<(), DatabaseError>;
+
+  fn user(&self, username: &str) -> Result<Option<User>, DatabaseError>;
+
+  /// Records a bearer token issued to `username` at login, by its
+  /// `auth::hash_token` hash. It stops working at `expires`.
+  fn add_token(&mut self, token_hash: &str, username: &str, expires: DateTime<Utc>) -> Result<(), DatabaseError>;
+
+  /// Username the unexpired token with `token_hash` was issued to.
+  fn token_user(&self, token_hash: &str) -> Result<Option<String>, DatabaseError>;
+
+  /// Posts ranked by relevance to `query.text`, see `SearchIndex`.
+  fn search(&self, query: &SearchQuery) -> Result<SearchPage, DatabaseError>;
+
+  /// Writes anything still buffered to disk, called once at shutdown.
+  fn flush(&mut self) -> Result<(), DatabaseError>{
+    Ok(())
+  }
+}
+
+/// Opens the storage backend selected in the config.
+pub fn open(backend: &Backend) -> Result<Box<dyn Database>, DatabaseError>{
+  match backend{
+    Backend::Memory => Ok(Box::new(MemoryDatabase::new())),
+    Backend::Sqlite(path) => Ok(Box::new(SqliteDatabase::open(path)?))
+  }
+}
+
+#[cfg(test)]
+pub(crate) mod tests{
+  use super::*;
+  use chrono::{Duration, TimeZone};
+  use crate::feed::Sort;
+
+  /// Runs the same CRUD checks against any backend.
+  pub(crate) fn check_crud(db: &mut dyn Database){
+    let id = Uuid::new_v4();
+    let post = Post::new("Title", "Body", "Raiden", Utc::now(), id);
+    db.add_post(post.clone()).unwrap();
+    db.add_post(Post::new("Other", "Body", "Fatbrad", Utc::now(), Uuid::new_v4())).unwrap();
+    assert!(matches!(db.add_post(post), Err(DatabaseError::Duplicate(d)) if d == id));
+    assert_eq!(db.posts().unwrap()[0].uuid(), &id);
+
+    let replaced = Post::new("New title", "New body", "Raiden", Utc::now(), id);
+    db.update_post(replaced).unwrap();
+
+    let patch: PostPatch = serde_json::from_str(r#"{"body": "Patched"}"#).unwrap();
+    let patched = serde_json::to_value(db.patch_post(&id, patch).unwrap()).unwrap();
+    assert_eq!((&patched["title"], &patched["body"]), (&"New title".into(), &"Patched".into()));
+    assert_eq!(serde_json::to_value(db.post(&id).unwrap()).unwrap(), patched);
+
+    assert!(db.delete_post(&id).is_ok());
+    assert!(matches!(db.delete_post(&id), Err(DatabaseError::NotFound(d)) if d == id));
+    assert!(db.post(&id).unwrap().is_none());
+    assert_eq!(db.posts().unwrap().len(), 1);
+    assert_eq!(db.count_posts().unwrap(), 1);
+  }
+
+  /// Runs the same feed queries against any backend.
+  pub(crate) fn check_feed(db: &mut dyn Database){
+    let start = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+    for i in 0..5{
+      let author = if i % 2 == 0 {"Raiden"} else {"Fatbrad"};
+      let title = format!("Post {}", i);
+      let post = Post::new(&title, "Rust and Iron", author, start + Duration::hours(i), Uuid::new_v4());
+      db.add_post(post).unwrap();
+    }
+    let titles = |page: &FeedPage| page.posts.iter().map(|p| p.post.title().to_string()).collect::<Vec<_>>();
+
+    let newest = db.feed(&FeedQuery {limit: 2, ..FeedQuery::default()}).unwrap();
+    assert_eq!(titles(&newest), ["Post 4", "Post 3"]);
+    assert_eq!(newest.pagination.total, 5);
+    let cursor = newest.pagination.next_cursor.unwrap();
+    let query = FeedQuery::parse([("limit", "2"), ("cursor", cursor.as_str())]).unwrap();
+    assert_eq!(titles(&db.feed(&query).unwrap()), ["Post 2", "Post 1"]);
+
+    let since = (start + Duration::hours(1)).to_rfc3339();
+    let query = FeedQuery::parse([("author", "Raiden"), ("since", since.as_str()), ("sort", "datetime")]).unwrap();
+    let page = db.feed(&query).unwrap();
+    assert_eq!(titles(&page), ["Post 2", "Post 4"]);
+    assert_eq!((page.pagination.total, page.pagination.next_cursor), (2, None));
+
+    let query = FeedQuery {search: Some("post 3".to_string()), sort: Sort::Oldest, ..FeedQuery::default()};
+    assert_eq!(titles(&db.feed(&query).unwrap()), ["Post 3"]);
+    let query = FeedQuery {until: Some(start), search: Some("IRON".to_string()), ..FeedQuery::default()};
+    assert_eq!(titles(&db.feed(&query).unwrap()), ["Post 0"]);
+  }
+
+  /// Runs the same user and token checks against any backend.
+  pub(crate) fn check_users(db: &mut dyn Database){
+    let user = User {username: "raiden".to_string(), password_hash: "hash".to_string()};
+    db.add_user(user.clone()).unwrap();
+    assert!(matches!(db.add_user(user), Err(DatabaseError::UserExists(name)) if name == "raiden"));
+    assert_eq!(db.user("raiden").unwrap().unwrap().password_hash, "hash");
+    assert!(db.user("fatbrad").unwrap().is_none());
+
+    db.add_token("hash", "raiden", Utc::now() + Duration::hours(1)).unwrap();
+    assert_eq!(db.token_user("hash").unwrap().as_deref(), Some("raiden"));
+    assert!(db.token_user("other").unwrap().is_none());
+    db.add_token("expired", "raiden", Utc::now() - Duration::seconds(1)).unwrap();
+    assert!(db.token_user("expired").unwrap().is_none());
+  }
+
+  /// Runs the same comment checks against any backend.
+  pub(crate) fn check_comments(db: &mut dyn Database){
+    let post = Post::new("Title", "Body", "Raiden", Utc::now(), Uuid::new_v4());
+    let id = *post.uuid();
+    db.add_post(post).unwrap();
+    let comment = |post: Uuid, body: &str| Comment {
+      uuid: Uuid::new_v4(), post, author: "Fatbrad".to_string(), body: body.to_string(), datetime: Utc::now()
+    };
+
+    let first = comment(id, "First");
+    db.add_comment(first.clone()).unwrap();
+    db.add_comment(comment(id, "Second")).unwrap();
+    let missing = Uuid::new_v4();
+    assert!(matches!(db.add_comment(comment(missing, "Lost")), Err(DatabaseError::NotFound(_))));
+    assert!(matches!(db.comments(&missing), Err(DatabaseError::NotFound(_))));
+
+    let bodies = |db: &dyn Database| db.comments(&id).unwrap().into_iter().map(|c| c.body).collect::<Vec<_>>();
+    assert_eq!(bodies(db), ["First", "Second"]);
+    assert_eq!(db.feed(&FeedQuery::default()).unwrap().posts[0].comment_count, 2);
+
+    assert_eq!(db.delete_comment(&id, &first.uuid).unwrap().body, "First");
+    assert!(matches!(db.delete_comment(&id, &first.uuid), Err(DatabaseError::CommentNotFound(_))));
+    assert_eq!(bodies(db), ["Second"]);
+
+    db.delete_post(&id).unwrap();
+    db.add_post(Post::new("Title", "Body", "Raiden", Utc::now(), id)).unwrap();
+    assert!(db.comments(&id).unwrap().is_empty());
+  }
+
+  /// Runs the same search checks against any backend.
+  pub(crate) fn check_search(db: &mut dyn Database){
+    let id = Uuid::new_v4();
+    db.add_post(Post::new("Iron handlers", "Routing requests in rust", "Raiden", Utc::now(), id)).unwrap();
+    db.add_post(Post::new("Rust for servers", "Why we picked rust", "Fatbrad", Utc::now(), Uuid::new_v4())).unwrap();
+    db.add_post(Post::new("Gardening", "Tomatoes", "Raiden", Utc::now(), Uuid::new_v4())).unwrap();
+
+    let search = |db: &dyn Database, text: &str|{
+      let query = SearchQuery::parse([("q", text)]).unwrap();
+      db.search(&query).unwrap()
+    };
+    let page = search(db, "rust");
+    assert_eq!((page.total, page.hits.len()), (2, 2));
+    assert_eq!(page.hits[0].highlights.title, "<mark>Rust</mark> for servers");
+    assert_eq!(page.hits[1].highlights.body, "Routing requests in <mark>rust</mark>");
+
+    let patch: PostPatch = serde_json::from_str(r#"{"body": "Routing requests"}"#).unwrap();
+    db.patch_post(&id, patch).unwrap();
+    assert_eq!(search(db, "rust").total, 1);
+    assert_eq!(search(db, "routing").hits[0].post.uuid(), &id);
+    db.delete_post(&id).unwrap();
+    assert_eq!(search(db, "routing").total, 0);
+  }
+}
@@ -0,0 +1,410 @@
+use std::path::Path;
+use chrono::{DateTime, Utc};
+use rusqlite::types::{Type, Value};
+use rusqlite::{params, params_from_iter, Connection, ErrorCode, OptionalExtension, Row, Transaction};
+use uuid::Uuid;
+
+use crate::auth::{hash_token, TOKEN_LIFETIME};
+use crate::database::{Database, DatabaseError};
+use crate::feed::{format_datetime, FeedPage, FeedPost, FeedQuery, Sort};
+use crate::models::{Comment, Post, User};
+use crate::search::{SearchIndex, SearchPage, SearchQuery};
+
+enum Migration{
+  Sql(&'static str),
+  Code(fn(&Transaction) -> rusqlite::Result<()>)
+}
+
+/// Schema changes in the order they were introduced. The number of applied
+/// migrations is kept in SQLite's `user_version`, so only newer entries run
+/// when an older database file is opened. Never edit an entry, append one.
+const MIGRATIONS: &[Migration] = &[
+  Migration::Sql("CREATE TABLE posts (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    uuid TEXT NOT NULL UNIQUE,
+    title TEXT NOT NULL,
+    body TEXT NOT NULL,
+    author TEXT NOT NULL,
+    datetime TEXT NOT NULL
+  )"),
+  Migration::Code(normalize_datetimes),
+  Migration::Sql("CREATE INDEX posts_datetime ON posts (datetime, uuid);
+    CREATE INDEX posts_author ON posts (author)"),
+  Migration::Sql("CREATE TABLE users (
+    username TEXT PRIMARY KEY,
+    password_hash TEXT NOT NULL
+  );
+  CREATE TABLE tokens (
+    token TEXT PRIMARY KEY,
+    username TEXT NOT NULL REFERENCES users (username),
+    created TEXT NOT NULL
+  )"),
+  Migration::Sql("CREATE TABLE comments (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    uuid TEXT NOT NULL UNIQUE,
+    post_uuid TEXT NOT NULL REFERENCES posts (uuid) ON DELETE CASCADE,
+    author TEXT NOT NULL,
+    body TEXT NOT NULL,
+    datetime TEXT NOT NULL
+  );
+  CREATE INDEX comments_post ON comments (post_uuid, id)"),
+  Migration::Code(hash_tokens)
+];
+
+/// Rewrites datetimes in the fixed width format of `format_datetime`, so
+/// the feed can compare and sort them as text.
+fn normalize_datetimes(transaction: &Transaction) -> rusqlite::Result<()>{
+  let rows = transaction
+    .prepare("SELECT id, datetime FROM posts")?
+    .query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))?
+    .collect::<rusqlite::Result<Vec<_>>>()?;
+  for (id, datetime) in rows{
+    let datetime = DateTime::parse_from_rfc3339(&datetime)
+      .map_err(|e| rusqlite::Error::FromSqlConversionFailure(1, Type::Text, Box::new(e)))?;
+    let datetime = format_datetime(&datetime.with_timezone(&Utc));
+    transaction.execute("UPDATE posts SET datetime = ?1 WHERE id = ?2", params![datetime, id])?;
+  }
+  Ok(())
+}
+
+/// Replaces plaintext tokens with their `hash_token` hash and gives them
+/// an expiry, `TOKEN_LIFETIME` after they were issued.
+fn hash_tokens(transaction: &Transaction) -> rusqlite::Result<()>{
+  let rows = transaction
+    .prepare("SELECT token, username, created FROM tokens")?
+    .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, get_datetime(row, 2)?)))?
+    .collect::<rusqlite::Result<Vec<_>>>()?;
+  transaction.execute_batch("DROP TABLE tokens;
+    CREATE TABLE tokens (
+      token_hash TEXT PRIMARY KEY,
+      username TEXT NOT NULL REFERENCES users (username),
+      created TEXT NOT NULL,
+      expires TEXT NOT NULL
+    )")?;
+  for (token, username, created) in rows{
+    transaction.execute(
+      "INSERT INTO tokens (token_hash, username, created, expires) VALUES (?1, ?2, ?3, ?4)",
+      params![hash_token(&token), username, format_datetime(&created), format_datetime(&(created + TOKEN_LIFETIME))]
+    )?;
+  }
+  Ok(())
+}
+
+const COLUMNS: &str = "title, body, author, datetime, uuid";
+
+/// Keeps posts in an SQLite database file. The search index is held in
+/// memory and rebuilt from the posts table on open.
+pub struct SqliteDatabase{
+  connection: Connection,
+  index: SearchIndex
+}
+
+impl SqliteDatabase{
+  pub fn open<P: AsRef<Path>>(path: P) -> Result<SqliteDatabase, DatabaseError>{
+    let mut database = SqliteDatabase {connection: Connection::open(path)?, index: SearchIndex::default()};
+    database.connection.pragma_update(None, "foreign_keys", true)?;
+    database.migrate()?;
+    for post in database.posts()?{
+      database.index.insert(&post);
+    }
+    Ok(database)
+  }
+
+  fn migrate(&mut self) -> Result<(), DatabaseError>{
+    let version: usize = self.connection.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    for (applied, migration) in MIGRATIONS.iter().enumerate().skip(version){
+      let transaction = self.connection.transaction()?;
+      match migration{
+        Migration::Sql(sql) => transaction.execute_batch(sql)?,
+        Migration::Code(migrate) => migrate(&transaction)?
+      }
+      transaction.pragma_update(None, "user_version", applied + 1)?;
+      transaction.commit()?;
+    }
+    Ok(())
+  }
+}
+
+fn get_datetime(row: &Row, index: usize) -> rusqlite::Result<DateTime<Utc>>{
+  let datetime: String = row.get(index)?;
+  DateTime::parse_from_rfc3339(&datetime)
+    .map(|d| d.with_timezone(&Utc))
+    .map_err(|e| rusqlite::Error::FromSqlConversionFailure(index, Type::Text, Box::new(e)))
+}
+
+fn get_uuid(row: &Row, index: usize) -> rusqlite::Result<Uuid>{
+  let uuid: String = row.get(index)?;
+  Uuid::parse_str(&uuid).map_err(|e| rusqlite::Error::FromSqlConversionFailure(index, Type::Text, Box::new(e)))
+}
+
+fn row_to_post(row: &Row) -> rusqlite::Result<Post>{
+  Ok(Post::new(
+    &row.get::<_, String>(0)?,
+    &row.get::<_, String>(1)?,
+    &row.get::<_, String>(2)?,
+    get_datetime(row, 3)?,
+    get_uuid(row, 4)?
+  ))
+}
+
+fn row_to_comment(row: &Row) -> rusqlite::Result<Comment>{
+  Ok(Comment{
+    uuid: get_uuid(row, 0)?,
+    post: get_uuid(row, 1)?,
+    author: row.get(2)?,
+    body: row.get(3)?,
+    datetime: get_datetime(row, 4)?
+  })
+}
+
+impl Database for SqliteDatabase{
+  fn add_post(&mut self, post: Post) -> Result<(), DatabaseError>{
+    let inserted = self.connection.execute(
+      &format!("INSERT INTO posts ({}) VALUES (?1, ?2, ?3, ?4, ?5)", COLUMNS),
+      params![post.title(), post.body(), post.author(), format_datetime(post.datetime()), post.uuid().to_string()]
+    );
+    match inserted{
+      Err(rusqlite::Error::SqliteFailure(e, _)) if e.code == ErrorCode::ConstraintViolation => {
+        Err(DatabaseError::Duplicate(*post.uuid()))
+      },
+      Err(e) => Err(e.into()),
+      Ok(_) => {
+        self.index.insert(&post);
+        Ok(())
+      }
+    }
+  }
+
+  fn posts(&self) -> Result<Vec<Post>, DatabaseError>{
+    let mut statement = self.connection.prepare(&format!("SELECT {} FROM posts ORDER BY id", COLUMNS))?;
+    let posts = statement.query_map([], row_to_post)?.collect::<rusqlite::Result<Vec<Post>>>()?;
+    Ok(posts)
+  }
+
+  fn count_posts(&self) -> Result<usize, DatabaseError>{
+    let count = self.connection.query_row("SELECT COUNT(*) FROM posts", [], |row| row.get(0))?;
+    Ok(count)
+  }
+
+  fn post(&self, id: &Uuid) -> Result<Option<Post>, DatabaseError>{
+    let post = self.connection.query_row(
+      &format!("SELECT {} FROM posts WHERE uuid = ?1", COLUMNS),
+      params![id.to_string()],
+      row_to_post
+    ).optional()?;
+    Ok(post)
+  }
+
+  fn feed(&self, query: &FeedQuery) -> Result<FeedPage, DatabaseError>{
+    let mut filters = vec!["1 = 1".to_string()];
+    let mut values: Vec<Value> = vec![];
+    if let Some(author) = &query.author{
+      values.push(Value::Text(author.clone()));
+      filters.push(format!("author = ?{}", values.len()));
+    }
+    if let Some(since) = &query.since{
+      values.push(Value::Text(format_datetime(since)));
+      filters.push(format!("datetime >= ?{}", values.len()));
+    }
+    if let Some(until) = &query.until{
+      values.push(Value::Text(format_datetime(until)));
+      filters.push(format!("datetime <= ?{}", values.len()));
+    }
+    if let Some(search) = &query.search{
+      let escaped = search.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+      values.push(Value::Text(format!("%{}%", escaped)));
+      let n = values.len();
+      filters.push(format!("(title LIKE ?{n} ESCAPE '\\' OR body LIKE ?{n} ESCAPE '\\')"));
+    }
+
+    let total: usize = self.connection.query_row(
+      &format!("SELECT COUNT(*) FROM posts WHERE {}", filters.join(" AND ")),
+      params_from_iter(&values),
+      |row| row.get(0)
+    )?;
+
+    let (compare, order) = match query.sort{
+      Sort::Oldest => (">", "ASC"),
+      Sort::Newest => ("<", "DESC")
+    };
+    if let Some(cursor) = &query.cursor{
+      values.push(Value::Text(format_datetime(&cursor.datetime)));
+      values.push(Value::Text(cursor.uuid.to_string()));
+      filters.push(format!("(datetime, uuid) {} (?{}, ?{})", compare, values.len() - 1, values.len()));
+    }
+    values.push(Value::Integer(query.limit as i64 + 1));
+    let sql = format!(
+      "SELECT {}, (SELECT COUNT(*) FROM comments WHERE comments.post_uuid = posts.uuid)
+      FROM posts WHERE {} ORDER BY datetime {order}, uuid {order} LIMIT ?{}",
+      COLUMNS, filters.join(" AND "), values.len()
+    );
+    let mut statement = self.connection.prepare(&sql)?;
+    let posts = statement
+      .query_map(params_from_iter(&values), |row| Ok(FeedPost {post: row_to_post(row)?, comment_count: row.get(5)?}))?
+      .collect::<rusqlite::Result<Vec<FeedPost>>>()?;
+    Ok(FeedPage::new(posts, query.limit, total))
+  }
+
+  fn update_post(&mut self, post: Post) -> Result<Post, DatabaseError>{
+    let updated = self.connection.execute(
+      "UPDATE posts SET title = ?1, body = ?2, author = ?3, datetime = ?4 WHERE uuid = ?5",
+      params![post.title(), post.body(), post.author(), format_datetime(post.datetime()), post.uuid().to_string()]
+    )?;
+    match updated{
+      0 => Err(DatabaseError::NotFound(*post.uuid())),
+      _ => {
+        self.index.insert(&post);
+        Ok(post)
+      }
+    }
+  }
+
+  fn delete_post(&mut self, id: &Uuid) -> Result<Post, DatabaseError>{
+    let post = self.post(id)?.ok_or(DatabaseError::NotFound(*id))?;
+    self.connection.execute("DELETE FROM posts WHERE uuid = ?1", params![id.to_string()])?;
+    self.index.remove(id);
+    Ok(post)
+  }
+
+  fn add_comment(&mut self, comment: Comment) -> Result<(), DatabaseError>{
+    if self.post(&comment.post)?.is_none(){
+      return Err(DatabaseError::NotFound(comment.post));
+    }
+    self.connection.execute(
+      "INSERT INTO comments (uuid, post_uuid, author, body, datetime) VALUES (?1, ?2, ?3, ?4, ?5)",
+      params![comment.uuid.to_string(), comment.post.to_string(), comment.author, comment.body, format_datetime(&comment.datetime)]
+    )?;
+    Ok(())
+  }
+
+  fn comments(&self, post: &Uuid) -> Result<Vec<Comment>, DatabaseError>{
+    if self.post(post)?.is_none(){
+      return Err(DatabaseError::NotFound(*post));
+    }
+    let mut statement = self.connection.prepare(
+      "SELECT uuid, post_uuid, author, body, datetime FROM comments WHERE post_uuid = ?1 ORDER BY id"
+    )?;
+    let comments = statement.query_map(params![post.to_string()], row_to_comment)?.collect::<rusqlite::Result<Vec<Comment>>>()?;
+    Ok(comments)
+  }
+
+  fn delete_comment(&mut self, post: &Uuid, id: &Uuid) -> Result<Comment, DatabaseError>{
+    let comment = self.connection.query_row(
+      "SELECT uuid, post_uuid, author, body, datetime FROM comments WHERE post_uuid = ?1 AND uuid = ?2",
+      params![post.to_string(), id.to_string()],
+      row_to_comment
+    ).optional()?.ok_or(DatabaseError::CommentNotFound(*id))?;
+    self.connection.execute("DELETE FROM comments WHERE uuid = ?1", params![id.to_string()])?;
+    Ok(comment)
+  }
+
+  fn add_user(&mut self, user: User) -> Result<(), DatabaseError>{
+    let inserted = self.connection.execute(
+      "INSERT INTO users (username, password_hash) VALUES (?1, ?2)",
+      params![user.username, user.password_hash]
+    );
+    match inserted{
+      Err(rusqlite::Error::SqliteFailure(e, _)) if e.code == ErrorCode::ConstraintViolation => {
+        Err(DatabaseError::UserExists(user.username))
+      },
+      Err(e) => Err(e.into()),
+      Ok(_) => Ok(())
+    }
+  }
+
+  fn user(&self, username: &str) -> Result<Option<User>, DatabaseError>{
+    let user = self.connection.query_row(
+      "SELECT username, password_hash FROM users WHERE username = ?1",
+      params![username],
+      |row| Ok(User {username: row.get(0)?, password_hash: row.get(1)?})
+    ).optional()?;
+    Ok(user)
+  }
+
+  fn add_token(&mut self, token_hash: &str, username: &str, expires: DateTime<Utc>) -> Result<(), DatabaseError>{
+    self.connection.execute(
+      "INSERT INTO tokens (token_hash, username, created, expires) VALUES (?1, ?2, ?3, ?4)",
+      params![token_hash, username, format_datetime(&Utc::now()), format_datetime(&expires)]
+    )?;
+    Ok(())
+  }
+
+  fn token_user(&self, token_hash: &str) -> Result<Option<String>, DatabaseError>{
+    let username = self.connection.query_row(
+      "SELECT username FROM tokens WHERE token_hash = ?1 AND expires > ?2",
+      params![token_hash, format_datetime(&Utc::now())],
+      |row| row.get(0)
+    ).optional()?;
+    Ok(username)
+  }
+
+  fn search(&self, query: &SearchQuery) -> Result<SearchPage, DatabaseError>{
+    SearchPage::collect(query, self.index.search(&query.text), |id| self.post(id))
+  }
+
+  fn flush(&mut self) -> Result<(), DatabaseError>{
+    self.connection.cache_flush()?;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests{
+  use super::*;
+  use crate::database::tests::{check_comments, check_crud, check_feed, check_search, check_users};
+
+  #[test]
+  fn test_crud_and_reopen(){
+    let path = std::env::temp_dir().join(format!("basic_web_api_{}.db", Uuid::new_v4()));
+    check_crud(&mut SqliteDatabase::open(&path).unwrap());
+
+    let reopened = SqliteDatabase::open(&path).unwrap();
+    assert_eq!(reopened.posts().unwrap().len(), 1);
+    let version: usize = reopened.connection.query_row("PRAGMA user_version", [], |row| row.get(0)).unwrap();
+    assert_eq!(version, MIGRATIONS.len());
+    std::fs::remove_file(path).unwrap();
+  }
+
+  #[test]
+  fn test_plaintext_tokens_are_hashed(){
+    let path = std::env::temp_dir().join(format!("basic_web_api_{}.db", Uuid::new_v4()));
+    let connection = Connection::open(&path).unwrap();
+    for migration in &MIGRATIONS[..5]{
+      match migration{
+        Migration::Sql(sql) => connection.execute_batch(sql).unwrap(),
+        Migration::Code(_) => {}
+      }
+    }
+    connection.pragma_update(None, "user_version", 5).unwrap();
+    connection.execute_batch("INSERT INTO users VALUES ('raiden', 'hash');
+      INSERT INTO tokens VALUES ('recent', 'raiden', '2100-01-01T00:00:00.000000000Z');
+      INSERT INTO tokens VALUES ('old', 'raiden', '2000-01-01T00:00:00.000000000Z')").unwrap();
+    drop(connection);
+
+    let database = SqliteDatabase::open(&path).unwrap();
+    assert_eq!(database.token_user(&hash_token("recent")).unwrap().as_deref(), Some("raiden"));
+    assert!(database.token_user("recent").unwrap().is_none());
+    assert!(database.token_user(&hash_token("old")).unwrap().is_none());
+    std::fs::remove_file(path).unwrap();
+  }
+
+  #[test]
+  fn test_feed(){
+    check_feed(&mut SqliteDatabase::open(":memory:").unwrap());
+  }
+
+  #[test]
+  fn test_users(){
+    check_users(&mut SqliteDatabase::open(":memory:").unwrap());
+  }
+
+  #[test]
+  fn test_comments(){
+    check_comments(&mut SqliteDatabase::open(":memory:").unwrap());
+  }
+
+  #[test]
+  fn test_search(){
+    check_search(&mut SqliteDatabase::open(":memory:").unwrap());
+  }
+}
@@ -0,0 +1,151 @@
+use std::error::Error;
+use std::fmt;
+use iron::headers::EntityTag;
+use iron::{status, IronError, Response};
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::database::DatabaseError;
+
+/// A rejected field of a request body.
+#[derive(Clone, Debug, PartialEq, Serialize, JsonSchema)]
+pub struct FieldError{
+  pub field: &'static str,
+  pub message: String
+}
+
+impl FieldError{
+  pub fn new(field: &'static str, message: &str) -> FieldError{
+    FieldError {field, message: message.to_string()}
+  }
+}
+
+/// Body of every error response:
+/// `{"code": "...", "message": "...", "fields": [{"field": "...", "message": "..."}]}`.
+#[derive(Clone, Debug, Serialize, JsonSchema)]
+pub struct ApiError{
+  #[serde(skip)]
+  pub status: status::Status,
+  pub code: &'static str,
+  pub message: String,
+  pub fields: Vec<FieldError>
+}
+
+impl ApiError{
+  pub fn new(status: status::Status, code: &'static str, message: &str) -> ApiError{
+    ApiError {status, code, message: message.to_string(), fields: vec![]}
+  }
+
+  pub fn bad_request(message: String) -> ApiError{
+    ApiError::new(status::BadRequest, "bad_request", &message)
+  }
+
+  pub fn invalid_json(message: String) -> ApiError{
+    ApiError::new(status::BadRequest, "invalid_json", &message)
+  }
+
+  pub fn not_found(message: String) -> ApiError{
+    ApiError::new(status::NotFound, "not_found", &message)
+  }
+
+  pub fn conflict(message: String) -> ApiError{
+    ApiError::new(status::Conflict, "conflict", &message)
+  }
+
+  pub fn unauthorized(message: String) -> ApiError{
+    ApiError::new(status::Unauthorized, "unauthorized", &message)
+  }
+
+  pub fn forbidden(message: String) -> ApiError{
+    ApiError::new(status::Forbidden, "forbidden", &message)
+  }
+
+  /// The post changed since the client read it. `message` names the
+  /// current tag so the client can refetch and retry.
+  pub fn precondition_failed(current: EntityTag) -> ApiError{
+    ApiError::new(status::PreconditionFailed, "precondition_failed", &format!("resource was modified, its current ETag is {}", current))
+  }
+
+  pub fn precondition_required() -> ApiError{
+    ApiError::new(status::PreconditionRequired, "precondition_required", "updates require an If-Match header with the ETag of the resource")
+  }
+
+  pub fn payload_too_large(max_bytes: u64) -> ApiError{
+    ApiError::new(status::PayloadTooLarge, "payload_too_large", &format!("request body is larger than {} bytes", max_bytes))
+  }
+
+  pub fn too_many_requests(retry_after: u64) -> ApiError{
+    ApiError::new(status::TooManyRequests, "too_many_requests", &format!("rate limit exceeded, retry in {} seconds", retry_after))
+  }
+
+  pub fn unavailable(message: String) -> ApiError{
+    ApiError::new(status::ServiceUnavailable, "unavailable", &message)
+  }
+
+  pub fn internal(message: String) -> ApiError{
+    ApiError::new(status::InternalServerError, "internal_error", &message)
+  }
+
+  pub fn validation(fields: Vec<FieldError>) -> ApiError{
+    ApiError{
+      fields,
+      ..ApiError::new(status::UnprocessableEntity, "validation_failed", "request body has invalid fields")
+    }
+  }
+
+  /// Generic error for a status set outside the handlers, e.g. the router's
+  /// 404 for unknown paths.
+  pub fn from_status(status: status::Status) -> ApiError{
+    let reason = status.canonical_reason().unwrap_or("Error");
+    let code = match status{
+      status::NotFound => "not_found",
+      status::MethodNotAllowed => "method_not_allowed",
+      status::BadRequest => "bad_request",
+      status::Unauthorized => "unauthorized",
+      status::Forbidden => "forbidden",
+      status::PreconditionFailed => "precondition_failed",
+      _ if status.is_client_error() => "client_error",
+      _ => "internal_error"
+    };
+    ApiError::new(status, code, reason)
+  }
+}
+
+impl From<DatabaseError> for ApiError{
+  fn from(error: DatabaseError) -> ApiError{
+    match error{
+      DatabaseError::NotFound(_) | DatabaseError::CommentNotFound(_) => ApiError::not_found(error.to_string()),
+      DatabaseError::Duplicate(_) | DatabaseError::UserExists(_) => ApiError::conflict(error.to_string()),
+      DatabaseError::Sqlite(_) => ApiError::internal(error.to_string())
+    }
+  }
+}
+
+impl fmt::Display for ApiError{
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result{
+    write!(f, "{}: {}", self.code, self.message)
+  }
+}
+
+impl Error for ApiError{}
+
+impl From<DatabaseError> for IronError{
+  fn from(error: DatabaseError) -> IronError{
+    ApiError::from(error).into()
+  }
+}
+
+impl From<ApiError> for IronError{
+  fn from(error: ApiError) -> IronError{
+    let payload = serde_json::to_string(&error).unwrap();
+    let status = error.status;
+    IronError::new(error, (status, payload))
+  }
+}
+
+impl From<ApiError> for Response{
+  fn from(error: ApiError) -> Response{
+    let payload = serde_json::to_string(&error).unwrap();
+    Response::with((error.status, payload))
+  }
+}
@@ -0,0 +1,132 @@
+use std::fmt::Write;
+use iron::headers::{EntityTag, ETag, IfMatch, IfNoneMatch};
+use iron::{status, Request, Response};
+use sha2::{Digest, Sha256};
+
+use crate::error::ApiError;
+
+/// Strong entity tag of a response body. Post bodies are the serialized
+/// post, so the tag changes whenever any of its fields do.
+pub fn of(payload: &str) -> EntityTag{
+  let mut tag = String::with_capacity(32);
+  for b in &Sha256::digest(payload.as_bytes())[..16]{
+    write!(&mut tag, "{:02x}", b).expect("unable to write");
+  }
+  EntityTag::strong(tag)
+}
+
+/// Response carrying `payload` and its `ETag`.
+pub fn tagged(status: status::Status, payload: String) -> Response{
+  let tag = of(&payload);
+  let mut res = Response::with((status, payload));
+  res.headers.set(ETag(tag));
+  res
+}
+
+/// `200` with `payload`, or an empty `304` when the client's `If-None-Match`
+/// already names its tag.
+pub fn conditional_get(req: &Request, payload: String) -> Response{
+  let tag = of(&payload);
+  let cached = match req.headers.get::<IfNoneMatch>(){
+    Some(IfNoneMatch::Any) => true,
+    Some(IfNoneMatch::Items(tags)) => tags.iter().any(|t| t.weak_eq(&tag)),
+    None => false
+  };
+  if !cached{
+    return tagged(status::Ok, payload);
+  }
+  let mut res = Response::with(status::NotModified);
+  res.headers.set(ETag(tag));
+  res
+}
+
+/// Updates must name the version they were made against in `If-Match`, so
+/// concurrent editors can't overwrite each other's changes.
+pub fn check_if_match(req: &Request, current: &str) -> Result<(), ApiError>{
+  let tag = of(current);
+  match req.headers.get::<IfMatch>(){
+    Some(IfMatch::Any) => Ok(()),
+    Some(IfMatch::Items(tags)) if tags.iter().any(|t| t.strong_eq(&tag)) => Ok(()),
+    Some(IfMatch::Items(_)) => Err(ApiError::precondition_failed(tag)),
+    None => Err(ApiError::precondition_required())
+  }
+}
+
+#[cfg(test)]
+mod tests{
+  use super::*;
+  use iron::headers::ETag;
+  use crate::testing::{self, send};
+
+  #[test]
+  fn test_tags_follow_content(){
+    assert_eq!(of("{\"title\":\"a\"}"), of("{\"title\":\"a\"}"));
+    assert_ne!(of("{\"title\":\"a\"}"), of("{\"title\":\"b\"}"));
+    assert!(!of("").weak);
+    assert_eq!(of("").tag().len(), 32);
+  }
+
+  fn etag(res: &testing::TestResponse) -> String{
+    let ETag(tag) = res.headers.get::<ETag>().unwrap();
+    tag.to_string()
+  }
+
+  #[test]
+  fn test_conditional_gets(){
+    let app = testing::app();
+    let auth = testing::login(&app, "alice");
+    let post = testing::create_post(&app, &auth);
+    let path = format!("/post/{}", post["uuid"].as_str().unwrap());
+
+    for path in [path.as_str(), "/post_feed"]{
+      let res = send(&app, "GET", path, &[], "");
+      assert_eq!(res.status, status::Ok);
+      let tag = etag(&res);
+
+      let res = send(&app, "GET", path, &[("If-None-Match", &tag)], "");
+      assert_eq!(res.status, status::NotModified, "{}", path);
+      assert_eq!(etag(&res), tag);
+      assert!(res.body.is_empty());
+      let res = send(&app, "GET", path, &[("If-None-Match", &format!("\"other\", W/{}", tag))], "");
+      assert_eq!(res.status, status::NotModified, "{}", path);
+      assert_eq!(send(&app, "GET", path, &[("If-None-Match", "\"other\"")], "").status, status::Ok);
+    }
+
+    // A new post changes the feed, so its old tag no longer matches.
+    let tag = etag(&send(&app, "GET", "/post_feed", &[], ""));
+    testing::create_post(&app, &auth);
+    assert_eq!(send(&app, "GET", "/post_feed", &[("If-None-Match", &tag)], "").status, status::Ok);
+  }
+
+  #[test]
+  fn test_updates_require_a_current_if_match(){
+    let app = testing::app();
+    let auth = testing::login(&app, "alice");
+    let post = testing::create_post(&app, &auth);
+    let path = format!("/post/{}", post["uuid"].as_str().unwrap());
+    let fields = r#"{"title": "New", "body": "Text"}"#;
+    let tag = etag(&send(&app, "GET", &path, &[], ""));
+
+    for method in ["PUT", "PATCH"]{
+      let res = send(&app, method, &path, &[("Authorization", &auth)], fields);
+      assert_eq!(res.status, status::PreconditionRequired, "{}", method);
+      assert_eq!(res.json()["code"], "precondition_required");
+    }
+
+    let res = send(&app, "PUT", &path, &[("Authorization", &auth), ("If-Match", &tag)], fields);
+    assert_eq!(res.status, status::Ok);
+    let updated = etag(&res);
+    assert_ne!(updated, tag);
+    assert_eq!(etag(&send(&app, "GET", &path, &[], "")), updated);
+
+    for method in ["PUT", "PATCH"]{
+      let res = send(&app, method, &path, &[("Authorization", &auth), ("If-Match", &tag)], r#"{"title": "Lost update", "body": "Text"}"#);
+      assert_eq!(res.status, status::PreconditionFailed, "{}", method);
+      assert!(res.json()["message"].as_str().unwrap().contains(&updated));
+    }
+
+    let res = send(&app, "PATCH", &path, &[("Authorization", &auth), ("If-Match", "*")], r#"{"title": "Any"}"#);
+    assert_eq!(res.status, status::Ok);
+    assert_eq!(res.json()["title"], "Any");
+  }
+}
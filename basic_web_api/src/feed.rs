@@ -0,0 +1,184 @@
+use std::fmt;
+use chrono::{DateTime, SecondsFormat, Utc};
+use schemars::JsonSchema;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::models::Post;
+
+pub const DEFAULT_LIMIT: usize = 20;
+pub const MAX_LIMIT: usize = 100;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Sort{
+  Oldest,
+  Newest
+}
+
+/// Position after the last post of a page. Posts are ordered by datetime and
+/// then uuid, so the pair is unique and stays valid while posts are added.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cursor{
+  pub datetime: DateTime<Utc>,
+  pub uuid: Uuid
+}
+
+impl Cursor{
+  pub fn after(post: &Post) -> Cursor{
+    Cursor {datetime: *post.datetime(), uuid: *post.uuid()}
+  }
+
+  fn parse(s: &str) -> Option<Cursor>{
+    let (datetime, uuid) = s.split_once('_')?;
+    Some(Cursor{
+      datetime: DateTime::parse_from_rfc3339(datetime).ok()?.with_timezone(&Utc),
+      uuid: Uuid::parse_str(uuid).ok()?
+    })
+  }
+}
+
+impl fmt::Display for Cursor{
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result{
+    write!(f, "{}_{}", format_datetime(&self.datetime), self.uuid)
+  }
+}
+
+/// Fixed width RFC 3339, so stored datetimes sort correctly as text.
+pub fn format_datetime(datetime: &DateTime<Utc>) -> String{
+  datetime.to_rfc3339_opts(SecondsFormat::Nanos, true)
+}
+
+/// Query parameters of `GET /post_feed`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FeedQuery{
+  pub limit: usize,
+  pub cursor: Option<Cursor>,
+  pub author: Option<String>,
+  pub since: Option<DateTime<Utc>>,
+  pub until: Option<DateTime<Utc>>,
+  pub search: Option<String>,
+  pub sort: Sort
+}
+
+impl Default for FeedQuery{
+  fn default() -> FeedQuery{
+    FeedQuery{
+      limit: DEFAULT_LIMIT,
+      cursor: None,
+      author: None,
+      since: None,
+      until: None,
+      search: None,
+      sort: Sort::Newest
+    }
+  }
+}
+
+impl FeedQuery{
+  /// Parses `limit`, `cursor`, `author`, `since`, `until`, `q` and `sort`.
+  /// Unknown parameters are ignored.
+  pub fn parse<'a, I>(pairs: I) -> Result<FeedQuery, String>
+    where I: IntoIterator<Item = (&'a str, &'a str)>{
+    let mut query = FeedQuery::default();
+    for (key, value) in pairs{
+      match key{
+        "limit" => {
+          query.limit = match value.parse::<usize>(){
+            Ok(limit) if (1..=MAX_LIMIT).contains(&limit) => limit,
+            _ => return Err(format!("limit must be between 1 and {}", MAX_LIMIT))
+          }
+        },
+        "cursor" => query.cursor = Some(Cursor::parse(value).ok_or("invalid cursor")?),
+        "author" => query.author = Some(value.to_string()),
+        "since" => query.since = Some(parse_datetime(key, value)?),
+        "until" => query.until = Some(parse_datetime(key, value)?),
+        "q" => query.search = Some(value.to_string()),
+        "sort" => {
+          query.sort = match value{
+            "datetime" => Sort::Oldest,
+            "-datetime" => Sort::Newest,
+            _ => return Err("sort must be datetime or -datetime".to_string())
+          }
+        },
+        _ => {}
+      }
+    }
+    Ok(query)
+  }
+
+  /// Whether `post` passes the author, date range and search filters.
+  pub fn matches(&self, post: &Post) -> bool{
+    let contains = |text: &str, search: &str| text.to_ascii_lowercase().contains(&search.to_ascii_lowercase());
+    self.author.as_ref().is_none_or(|author| post.author() == author)
+      && self.since.is_none_or(|since| *post.datetime() >= since)
+      && self.until.is_none_or(|until| *post.datetime() <= until)
+      && self.search.as_ref().is_none_or(|search| contains(post.title(), search) || contains(post.body(), search))
+  }
+
+  /// Filters, sorts and pages `posts` in memory.
+  pub fn apply<F: Fn(&Post) -> usize>(&self, posts: &[Post], comment_count: F) -> FeedPage{
+    let mut matching: Vec<&Post> = posts.iter().filter(|p| self.matches(p)).collect();
+    matching.sort_by_key(|p| (*p.datetime(), *p.uuid()));
+    if self.sort == Sort::Newest{
+      matching.reverse();
+    }
+    let total = matching.len();
+
+    let after_cursor = matching.into_iter().filter(|p| match (&self.cursor, self.sort){
+      (None, _) => true,
+      (Some(c), Sort::Oldest) => (*p.datetime(), *p.uuid()) > (c.datetime, c.uuid),
+      (Some(c), Sort::Newest) => (*p.datetime(), *p.uuid()) < (c.datetime, c.uuid)
+    });
+    let page = after_cursor
+      .take(self.limit + 1)
+      .map(|p| FeedPost {post: p.clone(), comment_count: comment_count(p)})
+      .collect();
+    FeedPage::new(page, self.limit, total)
+  }
+}
+
+fn parse_datetime(key: &str, value: &str) -> Result<DateTime<Utc>, String>{
+  DateTime::parse_from_rfc3339(value)
+    .map(|d| d.with_timezone(&Utc))
+    .map_err(|_| format!("{} must be an RFC 3339 datetime", key))
+}
+
+#[derive(Clone, Debug, Serialize, JsonSchema)]
+pub struct Pagination{
+  pub limit: usize,
+  pub count: usize,
+  pub total: usize,
+  pub next_cursor: Option<String>
+}
+
+/// A feed entry: the post's fields plus its number of comments.
+#[derive(Clone, Debug, Serialize, JsonSchema)]
+pub struct FeedPost{
+  #[serde(flatten)]
+  pub post: Post,
+  pub comment_count: usize
+}
+
+/// One page of the feed with its pagination metadata.
+#[derive(Clone, Debug, Serialize, JsonSchema)]
+pub struct FeedPage{
+  pub posts: Vec<FeedPost>,
+  pub pagination: Pagination
+}
+
+impl FeedPage{
+  /// Builds the page from up to `limit + 1` posts, the extra one only
+  /// signals that another page follows.
+  pub fn new(mut posts: Vec<FeedPost>, limit: usize, total: usize) -> FeedPage{
+    let more = posts.len() > limit;
+    posts.truncate(limit);
+    let next_cursor = match more{
+      true => posts.last().map(|p| Cursor::after(&p.post).to_string()),
+      false => None
+    };
+    FeedPage{
+      pagination: Pagination {limit, count: posts.len(), total, next_cursor},
+      posts
+    }
+  }
+}
@@ -1,25 +1,43 @@
 use std::sync::{Arc, Mutex};
 use std::io::Read;
-use iron::{status, AfterMiddleware, Handler, IronResult, Request, Response};
+use iron::{status, AfterMiddleware, Handler, IronError, IronResult, Request, Response};
 use iron::headers::ContentType;
-use std::error::Error;
+use iron::mime::Mime;
+use chrono::Utc;
 use uuid::Uuid;
 use router::Router;
 
+use crate::auth::{self, CurrentUser};
 use crate::database::Database;
-use crate::models::Post;
+use crate::error::ApiError;
+use crate::etag;
+use crate::feed::FeedQuery;
+use crate::limits::MaxBodySize;
+use crate::metrics::Metrics;
+use crate::openapi;
+use crate::search::SearchQuery;
+use crate::models::{CommentFields, Credentials, Post, PostPatch, User};
 
 macro_rules! try_handler{
   ($e:expr) => {
     match $e {
       Ok(x) => x,
-      Err(e) => return Ok(Response::with((status::InternalServerError, e.description()))),
+      Err(e) => return Ok(ApiError::internal(e.to_string()).into()),
     }
   };
   ($e:expr, $error:expr) => {
     match $e{
       Ok(x) => x,
-      Err(e) => return Ok(Response::with(($error, e.description())))
+      Err(e) => return Ok($error(e.to_string()).into())
+    }
+  }
+}
+
+macro_rules! try_database{
+  ($e:expr) => {
+    match $e{
+      Ok(x) => x,
+      Err(e) => return Ok(ApiError::from(e).into())
     }
   }
 }
@@ -34,60 +52,165 @@ macro_rules! get_http_param{
       Some(router) => {
         match router.find($e){
           Some(v) => v,
-          None => return Ok(Response::with(status::BadRequest)),
+          None => return Ok(ApiError::bad_request(format!("missing parameter {}", $e)).into()),
         }
       },
-      None => return Ok(Response::with(status::InternalServerError))
+      None => return Ok(ApiError::internal("router is not available".to_string()).into())
     }
   }
 }
 
 
+macro_rules! get_uuid_param{
+  ($r:expr, $e:expr) => {
+    try_handler!(Uuid::parse_str(get_http_param!($r, $e)), ApiError::bad_request)
+  }
+}
+
+macro_rules! get_post_id{
+  ($r:expr) => {
+    get_uuid_param!($r, "id")
+  }
+}
+
+/// Reads the body as text, at most `MaxBodySize` bytes.
+macro_rules! read_body{
+  ($r:expr) => {{
+    let max = $r.extensions.get::<MaxBodySize>().copied().unwrap_or(u64::MAX);
+    let mut payload = String::new();
+    try_handler!($r.body.by_ref().take(max.saturating_add(1)).read_to_string(&mut payload), ApiError::bad_request);
+    if payload.len() as u64 > max{
+      return Ok(ApiError::payload_too_large(max).into());
+    }
+    payload
+  }}
+}
+
+macro_rules! current_user{
+  ($r:expr) => {
+    match $r.extensions.get::<CurrentUser>(){
+      Some(username) => username.clone(),
+      None => return Ok(ApiError::unauthorized("a bearer token is required".to_string()).into())
+    }
+  }
+}
+
+macro_rules! read_credentials{
+  ($r:expr) => {{
+    let payload = read_body!($r);
+    let credentials: Credentials = try_handler!(serde_json::from_str(payload.as_str()), ApiError::invalid_json);
+    credentials
+  }}
+}
+
+macro_rules! read_fields{
+  ($r:expr, $partial:expr) => {{
+    let payload = read_body!($r);
+    let fields: PostPatch = try_handler!(serde_json::from_str(payload.as_str()), ApiError::invalid_json);
+    match fields.validate($partial){
+      Ok(fields) => fields,
+      Err(errors) => return Ok(ApiError::validation(errors).into())
+    }
+  }}
+}
+
+
 pub struct Handlers{
   pub post_feed: PostFeedHandler,
+  pub search: SearchHandler,
   pub post_post: PostPostHandler,
-  pub post: PostHandler
+  pub post: PostHandler,
+  pub put_post: PutPostHandler,
+  pub patch_post: PatchPostHandler,
+  pub delete_post: DeletePostHandler,
+  pub comments: CommentsHandler,
+  pub post_comment: PostCommentHandler,
+  pub delete_comment: DeleteCommentHandler,
+  pub register: RegisterHandler,
+  pub login: LoginHandler,
+  pub openapi: OpenApiHandler,
+  pub healthz: HealthHandler,
+  pub metrics: MetricsHandler
 }
 
 
 impl Handlers{
-  pub fn new(db: Database) -> Handlers{
-    let database = Arc::new(Mutex::new(db));
+  pub fn new(database: Arc<Mutex<Box<dyn Database>>>, metrics: Metrics) -> Handlers{
     Handlers{
       post_feed: PostFeedHandler::new(database.clone()),
+      search: SearchHandler::new(database.clone()),
       post_post: PostPostHandler::new(database.clone()),
-      post: PostHandler::new(database.clone())
+      post: PostHandler::new(database.clone()),
+      put_post: PutPostHandler::new(database.clone()),
+      patch_post: PatchPostHandler::new(database.clone()),
+      delete_post: DeletePostHandler::new(database.clone()),
+      comments: CommentsHandler::new(database.clone()),
+      post_comment: PostCommentHandler::new(database.clone()),
+      delete_comment: DeleteCommentHandler::new(database.clone()),
+      register: RegisterHandler::new(database.clone()),
+      login: LoginHandler::new(database.clone()),
+      openapi: OpenApiHandler::new(),
+      healthz: HealthHandler::new(database.clone()),
+      metrics: MetricsHandler::new(database.clone(), metrics)
     }
   }
 }
 
 
 pub struct PostFeedHandler{
-  database: Arc<Mutex<Database>>
+  database: Arc<Mutex<Box<dyn Database>>>
 }
 
 impl PostFeedHandler{
-  fn new(database: Arc<Mutex<Database>>) -> PostFeedHandler{
+  fn new(database: Arc<Mutex<Box<dyn Database>>>) -> PostFeedHandler{
     PostFeedHandler {database}
   }
 }
 
 
 impl Handler for PostFeedHandler{
-  fn handle(&self, _: &mut Request) -> IronResult<Response>{
-    let payload = try_handler!(serde_json::to_string(lock!(self.database).posts()));
+  fn handle(&self, req: &mut Request) -> IronResult<Response>{
+    let url: &iron::url::Url = req.url.as_ref();
+    let pairs: Vec<(String, String)> = url.query_pairs().into_owned().collect();
+    let query = try_handler!(FeedQuery::parse(pairs.iter().map(|(k, v)| (k.as_str(), v.as_str()))), ApiError::bad_request);
+    let page = try_database!(lock!(self.database).feed(&query));
+    let payload = try_handler!(serde_json::to_string(&page));
+    Ok(etag::conditional_get(req, payload))
+  }
+}
+
+
+/// Ranks posts by how well their title and body match `q`.
+pub struct SearchHandler{
+  database: Arc<Mutex<Box<dyn Database>>>
+}
+
+impl SearchHandler{
+  fn new(database: Arc<Mutex<Box<dyn Database>>>) -> SearchHandler{
+    SearchHandler {database}
+  }
+}
+
+
+impl Handler for SearchHandler{
+  fn handle(&self, req: &mut Request) -> IronResult<Response>{
+    let url: &iron::url::Url = req.url.as_ref();
+    let pairs: Vec<(String, String)> = url.query_pairs().into_owned().collect();
+    let query = try_handler!(SearchQuery::parse(pairs.iter().map(|(k, v)| (k.as_str(), v.as_str()))), ApiError::bad_request);
+    let page = try_database!(lock!(self.database).search(&query));
+    let payload = try_handler!(serde_json::to_string(&page));
     Ok(Response::with((status::Ok, payload)))
   }
 }
 
 
 pub struct PostPostHandler{
-  database: Arc<Mutex<Database>>
+  database: Arc<Mutex<Box<dyn Database>>>
 }
 
 
 impl PostPostHandler{
-  fn new(database: Arc<Mutex<Database>>) -> PostPostHandler {
+  fn new(database: Arc<Mutex<Box<dyn Database>>>) -> PostPostHandler {
     PostPostHandler {database}
   }
 }
@@ -95,54 +218,460 @@ impl PostPostHandler{
 
 impl Handler for PostPostHandler{
   fn handle(&self, req: &mut Request) -> IronResult<Response>{
-    let mut payload = String::new();
-    try_handler!(req.body.read_to_string(&mut payload));
-    let post = try_handler!(serde_json::from_str(payload.as_str()), status::BadRequest);
-    lock!(self.database).add_post(post);
-    Ok(Response::with((status::Created, payload)))
+    let author = current_user!(req);
+    let post = Post::create(read_fields!(req, false), &author);
+    try_database!(lock!(self.database).add_post(post.clone()));
+    let payload = try_handler!(serde_json::to_string(&post));
+    Ok(etag::tagged(status::Created, payload))
   }
 }
 
 
 pub struct PostHandler{
-  database: Arc<Mutex<Database>>
+  database: Arc<Mutex<Box<dyn Database>>>
 }
 
 
 impl PostHandler{
-  fn new(database: Arc<Mutex<Database>>) -> PostHandler{
+  fn new(database: Arc<Mutex<Box<dyn Database>>>) -> PostHandler{
     PostHandler {database}
   }
+}
+
 
-  fn find_post(&self, id: &Uuid) -> Option<Post>{
-    let locked = lock!(self.database);
-    let mut iterator = locked.posts().iter();
-    iterator.find(|p| p.uuid() == id).map(|p| p.clone())
+impl Handler for PostHandler{
+  fn handle(&self, req: &mut Request) -> IronResult<Response>{
+    let id = get_post_id!(req);
+    if let Some(post) = try_database!(lock!(self.database).post(&id)){
+      let payload = try_handler!(serde_json::to_string(&post));
+      Ok(etag::conditional_get(req, payload))
+    } else{
+      Ok(ApiError::not_found(format!("post {} not found", id)).into())
+    }
   }
 }
 
 
-impl Handler for PostHandler{
+/// Replaces every client editable field of the post.
+pub struct PutPostHandler{
+  database: Arc<Mutex<Box<dyn Database>>>
+}
+
+
+impl PutPostHandler{
+  fn new(database: Arc<Mutex<Box<dyn Database>>>) -> PutPostHandler{
+    PutPostHandler {database}
+  }
+}
+
+
+impl Handler for PutPostHandler{
   fn handle(&self, req: &mut Request) -> IronResult<Response>{
-    let ref post_id = get_http_param!(req, "id");
+    let id = get_post_id!(req);
+    let username = current_user!(req);
+    let fields = read_fields!(req, false);
+
+    let mut database = lock!(self.database);
+    let current = match check_owner(database.as_ref(), &id, &username){
+      Ok(post) => try_handler!(serde_json::to_string(&post)),
+      Err(e) => return Ok(e.into())
+    };
+    if let Err(e) = etag::check_if_match(req, &current){
+      return Ok(e.into());
+    }
+    let post = try_database!(database.patch_post(&id, fields));
+    let payload = try_handler!(serde_json::to_string(&post));
+    Ok(etag::tagged(status::Ok, payload))
+  }
+}
+
+
+pub struct PatchPostHandler{
+  database: Arc<Mutex<Box<dyn Database>>>
+}
+
+
+impl PatchPostHandler{
+  fn new(database: Arc<Mutex<Box<dyn Database>>>) -> PatchPostHandler{
+    PatchPostHandler {database}
+  }
+}
 
-    let id = try_handler!(Uuid::parse_str(post_id), status::BadRequest);
-    if let Some(post) = self.find_post(&id){
-      let payload = try_handler!(serde_json::to_string(&post), status::InternalServerError);
+
+impl Handler for PatchPostHandler{
+  fn handle(&self, req: &mut Request) -> IronResult<Response>{
+    let id = get_post_id!(req);
+    let username = current_user!(req);
+    let patch = read_fields!(req, true);
+
+    let mut database = lock!(self.database);
+    let current = match check_owner(database.as_ref(), &id, &username){
+      Ok(post) => try_handler!(serde_json::to_string(&post)),
+      Err(e) => return Ok(e.into())
+    };
+    if let Err(e) = etag::check_if_match(req, &current){
+      return Ok(e.into());
+    }
+    let post = try_database!(database.patch_post(&id, patch));
+    let payload = try_handler!(serde_json::to_string(&post));
+    Ok(etag::tagged(status::Ok, payload))
+  }
+}
+
+
+pub struct DeletePostHandler{
+  database: Arc<Mutex<Box<dyn Database>>>
+}
+
+
+impl DeletePostHandler{
+  fn new(database: Arc<Mutex<Box<dyn Database>>>) -> DeletePostHandler{
+    DeletePostHandler {database}
+  }
+}
+
+
+impl Handler for DeletePostHandler{
+  fn handle(&self, req: &mut Request) -> IronResult<Response>{
+    let id = get_post_id!(req);
+    let username = current_user!(req);
+
+    let mut database = lock!(self.database);
+    if let Err(e) = check_owner(database.as_ref(), &id, &username){
+      return Ok(e.into());
+    }
+    try_database!(database.delete_post(&id));
+    Ok(Response::with(status::NoContent))
+  }
+}
+
+
+pub struct CommentsHandler{
+  database: Arc<Mutex<Box<dyn Database>>>
+}
+
+
+impl CommentsHandler{
+  fn new(database: Arc<Mutex<Box<dyn Database>>>) -> CommentsHandler{
+    CommentsHandler {database}
+  }
+}
+
+
+impl Handler for CommentsHandler{
+  fn handle(&self, req: &mut Request) -> IronResult<Response>{
+    let id = get_post_id!(req);
+    let comments = try_database!(lock!(self.database).comments(&id));
+    let payload = try_handler!(serde_json::to_string(&comments));
     Ok(Response::with((status::Ok, payload)))
-    } else{
-      Ok(Response::with(status::NotFound))
+  }
+}
+
+
+pub struct PostCommentHandler{
+  database: Arc<Mutex<Box<dyn Database>>>
+}
+
+
+impl PostCommentHandler{
+  fn new(database: Arc<Mutex<Box<dyn Database>>>) -> PostCommentHandler{
+    PostCommentHandler {database}
+  }
+}
+
+
+impl Handler for PostCommentHandler{
+  fn handle(&self, req: &mut Request) -> IronResult<Response>{
+    let id = get_post_id!(req);
+    let author = current_user!(req);
+    let payload = read_body!(req);
+    let fields: CommentFields = try_handler!(serde_json::from_str(payload.as_str()), ApiError::invalid_json);
+    let comment = match fields.create(id, &author){
+      Ok(comment) => comment,
+      Err(errors) => return Ok(ApiError::validation(errors).into())
+    };
+
+    try_database!(lock!(self.database).add_comment(comment.clone()));
+    let payload = try_handler!(serde_json::to_string(&comment));
+    Ok(Response::with((status::Created, payload)))
+  }
+}
+
+
+/// Comments can be deleted by their author or by the author of the post.
+pub struct DeleteCommentHandler{
+  database: Arc<Mutex<Box<dyn Database>>>
+}
+
+
+impl DeleteCommentHandler{
+  fn new(database: Arc<Mutex<Box<dyn Database>>>) -> DeleteCommentHandler{
+    DeleteCommentHandler {database}
+  }
+}
+
+
+impl Handler for DeleteCommentHandler{
+  fn handle(&self, req: &mut Request) -> IronResult<Response>{
+    let id = get_post_id!(req);
+    let comment_id = get_uuid_param!(req, "cid");
+    let username = current_user!(req);
+
+    let mut database = lock!(self.database);
+    let post = match try_database!(database.post(&id)){
+      Some(post) => post,
+      None => return Ok(ApiError::not_found(format!("post {} not found", id)).into())
+    };
+    let comments = try_database!(database.comments(&id));
+    let comment = match comments.iter().find(|c| c.uuid == comment_id){
+      Some(comment) => comment,
+      None => return Ok(ApiError::not_found(format!("comment {} not found", comment_id)).into())
+    };
+    if comment.author != username && post.author() != username{
+      return Ok(ApiError::forbidden(format!("comment {} belongs to another author", comment_id)).into());
+    }
+    try_database!(database.delete_comment(&id, &comment_id));
+    Ok(Response::with(status::NoContent))
+  }
+}
+
+
+/// Only the author of a post may change or delete it. Returns the post as
+/// it is before the change.
+fn check_owner(database: &dyn Database, id: &Uuid, username: &str) -> Result<Post, ApiError>{
+  match database.post(id)?{
+    Some(post) if post.author() == username => Ok(post),
+    Some(_) => Err(ApiError::forbidden(format!("post {} belongs to another author", id))),
+    None => Err(ApiError::not_found(format!("post {} not found", id)))
+  }
+}
+
+
+pub struct RegisterHandler{
+  database: Arc<Mutex<Box<dyn Database>>>
+}
+
+
+impl RegisterHandler{
+  fn new(database: Arc<Mutex<Box<dyn Database>>>) -> RegisterHandler{
+    RegisterHandler {database}
+  }
+}
+
+
+impl Handler for RegisterHandler{
+  fn handle(&self, req: &mut Request) -> IronResult<Response>{
+    let credentials = read_credentials!(req);
+    if let Err(errors) = credentials.validate(){
+      return Ok(ApiError::validation(errors).into());
     }
+    let password_hash = try_handler!(auth::hash_password(&credentials.password));
+    let user = User {username: credentials.username, password_hash};
+    try_database!(lock!(self.database).add_user(user.clone()));
+    let payload = try_handler!(serde_json::to_string(&serde_json::json!({"username": user.username})));
+    Ok(Response::with((status::Created, payload)))
   }
 }
 
 
+/// Exchanges a username and password for a new bearer token.
+pub struct LoginHandler{
+  database: Arc<Mutex<Box<dyn Database>>>
+}
+
+
+impl LoginHandler{
+  fn new(database: Arc<Mutex<Box<dyn Database>>>) -> LoginHandler{
+    LoginHandler {database}
+  }
+}
+
+
+impl Handler for LoginHandler{
+  fn handle(&self, req: &mut Request) -> IronResult<Response>{
+    let credentials = read_credentials!(req);
+    let user = try_database!(lock!(self.database).user(&credentials.username));
+    let user = match user{
+      Some(user) if auth::verify_password(&credentials.password, &user.password_hash) => user,
+      _ => return Ok(ApiError::unauthorized("invalid username or password".to_string()).into())
+    };
+
+    let token = auth::new_token();
+    let expires = Utc::now() + auth::TOKEN_LIFETIME;
+    try_database!(lock!(self.database).add_token(&auth::hash_token(&token), &user.username, expires));
+    let payload = try_handler!(serde_json::to_string(&serde_json::json!({"username": user.username, "token": token})));
+    Ok(Response::with((status::Ok, payload)))
+  }
+}
+
+
+/// Serves the OpenAPI document, rendered once at startup.
+pub struct OpenApiHandler{
+  payload: String
+}
+
+
+impl OpenApiHandler{
+  fn new() -> OpenApiHandler{
+    OpenApiHandler {payload: openapi::spec().to_string()}
+  }
+}
+
+
+impl Handler for OpenApiHandler{
+  fn handle(&self, _: &mut Request) -> IronResult<Response>{
+    Ok(Response::with((status::Ok, self.payload.clone())))
+  }
+}
+
+
+/// Reports whether the storage answers queries, for load balancers and
+/// orchestrators.
+pub struct HealthHandler{
+  database: Arc<Mutex<Box<dyn Database>>>
+}
+
+
+impl HealthHandler{
+  fn new(database: Arc<Mutex<Box<dyn Database>>>) -> HealthHandler{
+    HealthHandler {database}
+  }
+}
+
+
+impl Handler for HealthHandler{
+  fn handle(&self, _: &mut Request) -> IronResult<Response>{
+    match lock!(self.database).count_posts(){
+      Ok(_) => Ok(Response::with((status::Ok, r#"{"status":"ok"}"#))),
+      Err(e) => Ok(ApiError::unavailable(e.to_string()).into())
+    }
+  }
+}
+
+
+/// Serves the request metrics and post count for Prometheus to scrape.
+pub struct MetricsHandler{
+  database: Arc<Mutex<Box<dyn Database>>>,
+  metrics: Metrics
+}
+
+
+impl MetricsHandler{
+  fn new(database: Arc<Mutex<Box<dyn Database>>>, metrics: Metrics) -> MetricsHandler{
+    MetricsHandler {database, metrics}
+  }
+}
+
+
+impl Handler for MetricsHandler{
+  fn handle(&self, _: &mut Request) -> IronResult<Response>{
+    let posts = try_database!(lock!(self.database).count_posts());
+    let text: Mime = "text/plain; version=0.0.4; charset=utf-8".parse().unwrap();
+    Ok(Response::with((status::Ok, text, self.metrics.render(posts))))
+  }
+}
+
+
+/// Marks every response with a body as JSON unless the handler set another
+/// content type. Errors raised outside the handlers, such as the router's
+/// 404, are turned into JSON error bodies.
 pub struct JsonAfterMiddleware;
 
 
 impl AfterMiddleware for JsonAfterMiddleware{
   fn after(&self, _: &mut Request, mut res: Response) -> IronResult<Response>{
-    res.headers.set(ContentType::json());
+    if res.body.is_some() && !res.headers.has::<ContentType>(){
+      res.headers.set(ContentType::json());
+    }
     Ok(res)
   }
-}
\ No newline at end of file
+
+  fn catch(&self, req: &mut Request, err: IronError) -> IronResult<Response>{
+    if err.response.body.is_some(){
+      return self.after(req, err.response);
+    }
+    let status = err.response.status.unwrap_or(status::InternalServerError);
+    let mut res: Response = ApiError::from_status(status).into();
+    res.headers = err.response.headers;
+    self.after(req, res)
+  }
+}
+#[cfg(test)]
+mod tests{
+  use iron::status;
+  use crate::testing::{self, send};
+
+  #[test]
+  fn test_only_the_author_changes_a_post(){
+    let app = testing::app();
+    let alice = testing::login(&app, "alice");
+    let bob = testing::login(&app, "bob");
+    let post = testing::create_post(&app, &alice);
+    assert_eq!(post["author"], "alice");
+    let path = format!("/post/{}", post["uuid"].as_str().unwrap());
+    let fields = r#"{"title": "New", "body": "Text"}"#;
+
+    for method in ["PUT", "PATCH"]{
+      let res = send(&app, method, &path, &[("If-Match", "*")], fields);
+      assert_eq!(res.status, status::Unauthorized, "{}", method);
+      let res = send(&app, method, &path, &[("Authorization", &bob), ("If-Match", "*")], fields);
+      assert_eq!(res.status, status::Forbidden, "{}", method);
+      assert_eq!(res.json()["code"], "forbidden");
+      let res = send(&app, method, &path, &[("Authorization", &alice), ("If-Match", "*")], fields);
+      assert_eq!(res.status, status::Ok, "{}", method);
+      assert_eq!(res.json()["author"], "alice");
+    }
+
+    assert_eq!(send(&app, "DELETE", &path, &[], "").status, status::Unauthorized);
+    assert_eq!(send(&app, "DELETE", &path, &[("Authorization", &bob)], "").status, status::Forbidden);
+    assert_eq!(send(&app, "GET", &path, &[], "").status, status::Ok);
+    let res = send(&app, "DELETE", &path, &[("Authorization", &alice)], "");
+    assert_eq!(res.status, status::NoContent);
+    assert!(res.body.is_empty());
+
+    assert_eq!(send(&app, "GET", &path, &[], "").status, status::NotFound);
+    assert_eq!(send(&app, "DELETE", &path, &[("Authorization", &alice)], "").status, status::NotFound);
+    assert_eq!(send(&app, "GET", &format!("{}/comments", path), &[], "").status, status::NotFound);
+  }
+
+  #[test]
+  fn test_status_codes(){
+    let app = testing::app();
+    let credentials = r#"{"username": "alice", "password": "correct horse"}"#;
+    assert_eq!(send(&app, "POST", "/register", &[], credentials).status, status::Created);
+    let res = send(&app, "POST", "/register", &[], credentials);
+    assert_eq!(res.status, status::Conflict);
+    assert_eq!(res.json()["code"], "conflict");
+
+    let res = send(&app, "GET", &format!("/post/{}", uuid::Uuid::new_v4()), &[], "");
+    assert_eq!(res.status, status::NotFound);
+    assert_eq!(res.json()["code"], "not_found");
+    assert_eq!(send(&app, "GET", "/no/such/path", &[], "").json()["code"], "not_found");
+  }
+
+  #[test]
+  fn test_comments_are_deleted_by_their_author_or_the_post_author(){
+    let app = testing::app();
+    let alice = testing::login(&app, "alice");
+    let bob = testing::login(&app, "bob");
+    let carol = testing::login(&app, "carol");
+    let post = testing::create_post(&app, &alice);
+    let comments = format!("/post/{}/comments", post["uuid"].as_str().unwrap());
+    let comment = |auth: &str|{
+      let res = send(&app, "POST", &comments, &[("Authorization", auth)], r#"{"body": "Nice"}"#);
+      assert_eq!(res.status, status::Created);
+      format!("{}/{}", comments, res.json()["uuid"].as_str().unwrap())
+    };
+
+    let by_bob = comment(&bob);
+    assert_eq!(send(&app, "DELETE", &by_bob, &[], "").status, status::Unauthorized);
+    assert_eq!(send(&app, "DELETE", &by_bob, &[("Authorization", &carol)], "").status, status::Forbidden);
+    assert_eq!(send(&app, "DELETE", &by_bob, &[("Authorization", &bob)], "").status, status::NoContent);
+    assert_eq!(send(&app, "DELETE", &by_bob, &[("Authorization", &bob)], "").status, status::NotFound);
+
+    let by_carol = comment(&carol);
+    assert_eq!(send(&app, "DELETE", &by_carol, &[("Authorization", &bob)], "").status, status::Forbidden);
+    assert_eq!(send(&app, "DELETE", &by_carol, &[("Authorization", &alice)], "").status, status::NoContent);
+    assert_eq!(send(&app, "GET", &comments, &[], "").json(), serde_json::json!([]));
+  }
+}
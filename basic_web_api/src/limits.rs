@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use iron::headers::ContentLength;
+use iron::typemap::Key;
+use iron::{BeforeMiddleware, IronError, IronResult, Request};
+
+use crate::config::Limits;
+use crate::error::ApiError;
+
+/// Buckets are only tracked for this many clients, idle full buckets are
+/// dropped beyond it.
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+/// Token bucket of one client. It holds up to `burst` tokens, refills at
+/// `rate` tokens per second and every request takes one.
+#[derive(Clone, Debug)]
+struct Bucket{
+  tokens: f64,
+  updated: Instant
+}
+
+impl Bucket{
+  fn full(burst: f64, now: Instant) -> Bucket{
+    Bucket {tokens: burst, updated: now}
+  }
+
+  /// Takes a token, or returns how long until one is available.
+  fn take(&mut self, now: Instant, rate: f64, burst: f64) -> Result<(), Duration>{
+    let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+    self.tokens = (self.tokens + elapsed * rate).min(burst);
+    self.updated = now;
+    if self.tokens >= 1.0{
+      self.tokens -= 1.0;
+      Ok(())
+    } else{
+      Err(Duration::from_secs_f64((1.0 - self.tokens) / rate))
+    }
+  }
+
+  fn is_full(&self, now: Instant, rate: f64, burst: f64) -> bool{
+    self.tokens + now.saturating_duration_since(self.updated).as_secs_f64() * rate >= burst
+  }
+}
+
+/// Per client IP rate limiting. Requests over the limit get 429 with a
+/// `Retry-After` header in whole seconds.
+pub struct RateLimiter{
+  rate: f64,
+  burst: f64,
+  buckets: Mutex<HashMap<IpAddr, Bucket>>
+}
+
+impl RateLimiter{
+  pub fn new(limits: &Limits) -> RateLimiter{
+    RateLimiter{
+      rate: limits.requests_per_second,
+      burst: limits.burst as f64,
+      buckets: Mutex::new(HashMap::new())
+    }
+  }
+
+  fn check(&self, ip: IpAddr, now: Instant) -> Result<(), Duration>{
+    let mut buckets = self.buckets.lock().unwrap();
+    if buckets.len() >= MAX_TRACKED_CLIENTS{
+      buckets.retain(|_, bucket| !bucket.is_full(now, self.rate, self.burst));
+    }
+    buckets.entry(ip).or_insert_with(|| Bucket::full(self.burst, now)).take(now, self.rate, self.burst)
+  }
+}
+
+impl BeforeMiddleware for RateLimiter{
+  fn before(&self, req: &mut Request) -> IronResult<()>{
+    match self.check(req.remote_addr.ip(), Instant::now()){
+      Ok(()) => Ok(()),
+      Err(wait) => {
+        let seconds = wait.as_secs_f64().ceil().max(1.0) as u64;
+        let mut error: IronError = ApiError::too_many_requests(seconds).into();
+        error.response.headers.set_raw("Retry-After", vec![seconds.to_string().into_bytes()]);
+        Err(error)
+      }
+    }
+  }
+}
+
+/// Largest accepted request body in bytes, read by the handlers.
+pub struct MaxBodySize;
+
+impl Key for MaxBodySize{
+  type Value = u64;
+}
+
+/// Rejects bodies with a `Content-Length` over the limit with 413 before
+/// they are read. Bodies without one are cut off by the handlers while
+/// reading, using the limit stored in `MaxBodySize`.
+pub struct BodyLimit{
+  max_bytes: u64
+}
+
+impl BodyLimit{
+  pub fn new(limits: &Limits) -> BodyLimit{
+    BodyLimit {max_bytes: limits.max_body_bytes}
+  }
+}
+
+impl BeforeMiddleware for BodyLimit{
+  fn before(&self, req: &mut Request) -> IronResult<()>{
+    if let Some(ContentLength(length)) = req.headers.get::<ContentLength>(){
+      if *length > self.max_bytes{
+        return Err(ApiError::payload_too_large(self.max_bytes).into());
+      }
+    }
+    req.extensions.insert::<MaxBodySize>(self.max_bytes);
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests{
+  use super::*;
+
+  #[test]
+  fn test_token_bucket(){
+    let limiter = RateLimiter::new(&Limits {requests_per_second: 2.0, burst: 3, max_body_bytes: 0});
+    let ip: IpAddr = "127.0.0.1".parse().unwrap();
+    let start = Instant::now();
+    for _ in 0..3{
+      assert!(limiter.check(ip, start).is_ok());
+    }
+    assert_eq!(limiter.check(ip, start), Err(Duration::from_millis(500)));
+    assert!(limiter.check("127.0.0.2".parse().unwrap(), start).is_ok());
+
+    assert!(limiter.check(ip, start + Duration::from_millis(500)).is_ok());
+    assert!(limiter.check(ip, start + Duration::from_millis(500)).is_err());
+    assert!(limiter.check(ip, start + Duration::from_secs(60)).is_ok());
+  }
+}
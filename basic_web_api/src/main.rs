@@ -1,21 +1,101 @@
+mod auth;
+mod config;
+mod error;
+mod etag;
+mod feed;
 mod models;
 mod database;
 mod handlers;
+mod limits;
+mod metrics;
+mod openapi;
+mod routes;
+mod search;
+mod shutdown;
+#[cfg(test)]
+mod testing;
 
 use models::*;
-use database::Database;
+use config::{Config, Limits};
+use auth::AuthMiddleware;
+use database::{Database, DatabaseError};
 use handlers::*;
+use limits::{BodyLimit, RateLimiter};
+use metrics::Metrics;
 
 use iron::prelude::Chain;
 use iron::Iron;
-use router::Router;
+use std::process;
+use std::sync::{Arc, Mutex};
+use log::{error, info};
 use logger::Logger;
 use uuid::Uuid;
 
 fn main() {
-  env_logger::init();
+  let config = match Config::load(){
+    Ok(config) => config,
+    Err(e) => {
+      eprintln!("invalid configuration: {}", e);
+      process::exit(2);
+    }
+  };
+  env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(&config.log_level)).init();
+
+  let mut db = match database::open(&config.storage){
+    Ok(db) => db,
+    Err(e) => {
+      error!("can't open storage: {}", e);
+      process::exit(1);
+    }
+  };
+  if config.seed{
+    if let Err(e) = seed(db.as_mut()){
+      error!("can't seed storage: {}", e);
+      process::exit(1);
+    }
+  }
+
+  let database = Arc::new(Mutex::new(db));
+  let mut server = Iron::new(app(&config.limits, database.clone()));
+  server.threads = config.threads;
+  // Dropping `Listening` joins the server threads, so it is kept until exit.
+  let listening = match server.http(config.bind.as_str()){
+    Ok(listening) => listening,
+    Err(e) => {
+      error!("can't listen on {}: {}", config.bind, e);
+      process::exit(1);
+    }
+  };
+  info!("listening on {} with {} threads", listening.socket, config.threads);
+  shutdown::exit_on_signal(database);
+}
+
+/// The routes behind every middleware, as served.
+fn app(limits: &Limits, database: Arc<Mutex<Box<dyn Database>>>) -> Chain{
   let (logger_before, logger_after) = Logger::new(None);
-  let mut db = Database::new();
+  let metrics = Metrics::new();
+  let (metrics_before, metrics_after) = metrics.middleware();
+  let auth = AuthMiddleware::new(database.clone());
+  let json_content_middleware = JsonAfterMiddleware;
+  let router = routes::router(Handlers::new(database, metrics));
+
+  let mut chain = Chain::new(router);
+  chain.link_before(logger_before);
+  chain.link_before(metrics_before);
+  chain.link_before(RateLimiter::new(limits));
+  chain.link_before(BodyLimit::new(limits));
+  chain.link_before(auth);
+  chain.link_after(json_content_middleware);
+  chain.link_after(logger_after);
+  chain.link_after(metrics_after);
+  chain
+}
+
+/// Adds the sample posts to an empty database.
+fn seed(db: &mut dyn Database) -> Result<(), DatabaseError>{
+  if db.count_posts()? > 0{
+    return Ok(());
+  }
   let p1 = Post::new(
       "First Post",
       "First Api post",
@@ -23,7 +103,7 @@ fn main() {
       chrono::offset::Utc::now(),
       Uuid::new_v4()
     );
-  db.add_post(p1);
+  db.add_post(p1)?;
 
   let p2 = Post::new(
       "Next Post",
@@ -32,20 +112,5 @@ fn main() {
       chrono::offset::Utc::now(),
       Uuid::new_v4()
     );
-  db.add_post(p2);
-
-  let handlers = Handlers::new(db);
-  let json_content_middleware = JsonAfterMiddleware;
-
-  let mut router = Router::new();
-  router.get("/post_feed", handlers.post_feed, "post_feed");
-  router.post("/post", handlers.post_post, "post_post");
-  router.get("/post/:id", handlers.post, "post");
-
-  let mut chain = Chain::new(router);
-  chain.link_before(logger_before);
-  chain.link_after(json_content_middleware);
-  chain.link_after(logger_after);
-
-  Iron::new(chain).http("localhost:8080").unwrap();
+  db.add_post(p2)
 }
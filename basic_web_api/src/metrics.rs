@@ -0,0 +1,168 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use iron::typemap::Key;
+use iron::{status, AfterMiddleware, BeforeMiddleware, IronError, IronResult, Request, Response};
+
+use crate::routes;
+
+/// Upper bounds of the latency histogram buckets in seconds, the Prometheus
+/// client defaults.
+const BUCKETS: [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// Label for requests that match no route, so unknown paths can't grow the
+/// number of series.
+const UNMATCHED: &str = "unmatched";
+
+#[derive(Clone, Debug, Default)]
+struct Histogram{
+  /// Observations at or below each bound of `BUCKETS`, not cumulative.
+  buckets: [u64; BUCKETS.len()],
+  sum: f64,
+  count: u64
+}
+
+impl Histogram{
+  fn observe(&mut self, seconds: f64){
+    if let Some(i) = BUCKETS.iter().position(|bound| seconds <= *bound){
+      self.buckets[i] += 1;
+    }
+    self.sum += seconds;
+    self.count += 1;
+  }
+}
+
+#[derive(Debug, Default)]
+struct Registry{
+  /// Keyed by route name, method and status code.
+  requests: BTreeMap<(&'static str, String, u16), u64>,
+  /// Keyed by route name and method.
+  latencies: BTreeMap<(&'static str, String), Histogram>
+}
+
+/// Request counters and latencies, shared by the middleware that records
+/// them and the `/metrics` handler.
+#[derive(Clone, Debug, Default)]
+pub struct Metrics{
+  registry: Arc<Mutex<Registry>>
+}
+
+impl Metrics{
+  pub fn new() -> Metrics{
+    Metrics::default()
+  }
+
+  /// Before and after middleware recording every request, linked like the
+  /// `Logger` pair.
+  pub fn middleware(&self) -> (MetricsMiddleware, MetricsMiddleware){
+    (MetricsMiddleware {metrics: self.clone()}, MetricsMiddleware {metrics: self.clone()})
+  }
+
+  fn record(&self, route: &'static str, method: String, status: u16, seconds: f64){
+    let mut registry = self.registry.lock().unwrap();
+    *registry.requests.entry((route, method.clone(), status)).or_default() += 1;
+    registry.latencies.entry((route, method)).or_default().observe(seconds);
+  }
+
+  /// Prometheus text exposition format, version 0.0.4.
+  pub fn render(&self, posts: usize) -> String{
+    let registry = self.registry.lock().unwrap();
+    let mut out = String::new();
+
+    out.push_str("# HELP http_requests_total Requests handled, by route, method and status code.\n");
+    out.push_str("# TYPE http_requests_total counter\n");
+    for ((route, method, status), count) in &registry.requests{
+      writeln!(out, "http_requests_total{{route=\"{}\",method=\"{}\",status=\"{}\"}} {}", route, method, status, count).unwrap();
+    }
+
+    out.push_str("# HELP http_request_duration_seconds Time spent handling requests, by route and method.\n");
+    out.push_str("# TYPE http_request_duration_seconds histogram\n");
+    for ((route, method), histogram) in &registry.latencies{
+      let labels = format!("route=\"{}\",method=\"{}\"", route, method);
+      let mut cumulative = 0;
+      for (bound, count) in BUCKETS.iter().zip(histogram.buckets){
+        cumulative += count;
+        writeln!(out, "http_request_duration_seconds_bucket{{{},le=\"{}\"}} {}", labels, bound, cumulative).unwrap();
+      }
+      writeln!(out, "http_request_duration_seconds_bucket{{{},le=\"+Inf\"}} {}", labels, histogram.count).unwrap();
+      writeln!(out, "http_request_duration_seconds_sum{{{}}} {}", labels, histogram.sum).unwrap();
+      writeln!(out, "http_request_duration_seconds_count{{{}}} {}", labels, histogram.count).unwrap();
+    }
+
+    out.push_str("# HELP posts Posts in storage.\n");
+    out.push_str("# TYPE posts gauge\n");
+    writeln!(out, "posts {}", posts).unwrap();
+    out
+  }
+}
+
+struct RequestStart;
+
+impl Key for RequestStart{
+  type Value = Instant;
+}
+
+pub struct MetricsMiddleware{
+  metrics: Metrics
+}
+
+impl MetricsMiddleware{
+  fn observe(&self, req: &mut Request, res: &Response){
+    let start = match req.extensions.get::<RequestStart>(){
+      Some(start) => *start,
+      None => return
+    };
+    let path = req.url.path();
+    let route = routes::find(&req.method, &path).map(|r| r.name).unwrap_or(UNMATCHED);
+    let status = res.status.unwrap_or(status::NotFound).to_u16();
+    self.metrics.record(route, req.method.to_string(), status, start.elapsed().as_secs_f64());
+  }
+}
+
+impl BeforeMiddleware for MetricsMiddleware{
+  fn before(&self, req: &mut Request) -> IronResult<()>{
+    req.extensions.insert::<RequestStart>(Instant::now());
+    Ok(())
+  }
+
+  fn catch(&self, req: &mut Request, err: IronError) -> IronResult<()>{
+    req.extensions.insert::<RequestStart>(Instant::now());
+    Err(err)
+  }
+}
+
+impl AfterMiddleware for MetricsMiddleware{
+  fn after(&self, req: &mut Request, res: Response) -> IronResult<Response>{
+    self.observe(req, &res);
+    Ok(res)
+  }
+
+  fn catch(&self, req: &mut Request, err: IronError) -> IronResult<Response>{
+    self.observe(req, &err.response);
+    Err(err)
+  }
+}
+
+#[cfg(test)]
+mod tests{
+  use super::*;
+
+  #[test]
+  fn test_render(){
+    let metrics = Metrics::new();
+    metrics.record("post", "GET".to_string(), 200, 0.003);
+    metrics.record("post", "GET".to_string(), 200, 0.2);
+    metrics.record("post", "GET".to_string(), 404, 20.0);
+    let text = metrics.render(7);
+
+    assert!(text.contains("http_requests_total{route=\"post\",method=\"GET\",status=\"200\"} 2\n"));
+    assert!(text.contains("http_requests_total{route=\"post\",method=\"GET\",status=\"404\"} 1\n"));
+    assert!(text.contains("http_request_duration_seconds_bucket{route=\"post\",method=\"GET\",le=\"0.005\"} 1\n"));
+    assert!(text.contains("http_request_duration_seconds_bucket{route=\"post\",method=\"GET\",le=\"0.25\"} 2\n"));
+    assert!(text.contains("http_request_duration_seconds_bucket{route=\"post\",method=\"GET\",le=\"10\"} 2\n"));
+    assert!(text.contains("http_request_duration_seconds_bucket{route=\"post\",method=\"GET\",le=\"+Inf\"} 3\n"));
+    assert!(text.contains("http_request_duration_seconds_count{route=\"post\",method=\"GET\"} 3\n"));
+    assert!(text.ends_with("posts 7\n"));
+  }
+}
@@ -1,9 +1,19 @@
 use chrono::DateTime;
 use chrono::offset::Utc;
 use uuid::Uuid;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+use crate::error::FieldError;
+
+pub const MAX_TITLE_LENGTH: usize = 200;
+pub const MAX_BODY_LENGTH: usize = 10_000;
+pub const MAX_COMMENT_LENGTH: usize = 2_000;
+pub const MIN_USERNAME_LENGTH: usize = 3;
+pub const MAX_USERNAME_LENGTH: usize = 32;
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
 pub struct Post{
   title: String,
   body: String,
@@ -12,6 +22,112 @@ pub struct Post{
   uuid: Uuid
 }
 
+/// Post fields a client may send. `author`, `uuid` and `datetime` are always
+/// set by the server, so they are ignored if present. For `PATCH /post/:id`
+/// absent fields are left unchanged.
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema)]
+pub struct PostPatch{
+  title: Option<String>,
+  body: Option<String>
+}
+
+/// A comment on a post, deleted together with the post.
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Comment{
+  pub uuid: Uuid,
+  pub post: Uuid,
+  pub author: String,
+  pub body: String,
+  pub datetime: DateTime<Utc>
+}
+
+/// Body of `POST /post/:id/comments`, the rest is set by the server.
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+pub struct CommentFields{
+  body: Option<String>
+}
+
+impl CommentFields{
+  /// New comment by `author` on `post` if the trimmed body is non-empty and
+  /// within `MAX_COMMENT_LENGTH`.
+  pub fn create(self, post: Uuid, author: &str) -> Result<Comment, Vec<FieldError>>{
+    let body = self.body.unwrap_or_default().trim().to_string();
+    if body.is_empty(){
+      return Err(vec![FieldError::new("body", "is required")]);
+    }
+    if body.chars().count() > MAX_COMMENT_LENGTH{
+      return Err(vec![FieldError::new("body", &format!("must be at most {} characters", MAX_COMMENT_LENGTH))]);
+    }
+    Ok(Comment {uuid: Uuid::new_v4(), post, author: author.to_string(), body, datetime: Utc::now()})
+  }
+}
+
+/// A registered user. Posts are owned through their `author`, which is the
+/// username of the user who created them.
+#[derive(Clone, Debug)]
+pub struct User{
+  pub username: String,
+  pub password_hash: String
+}
+
+/// Body of `POST /register` and `POST /login`.
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+pub struct Credentials{
+  pub username: String,
+  pub password: String
+}
+
+impl Credentials{
+  /// Rules for new accounts: a short username of letters, digits, `_` or `-`
+  /// and a password of at least `MIN_PASSWORD_LENGTH` characters.
+  pub fn validate(&self) -> Result<(), Vec<FieldError>>{
+    let mut errors = vec![];
+    let length = self.username.chars().count();
+    if !(MIN_USERNAME_LENGTH..=MAX_USERNAME_LENGTH).contains(&length){
+      let message = format!("must be {} to {} characters", MIN_USERNAME_LENGTH, MAX_USERNAME_LENGTH);
+      errors.push(FieldError::new("username", &message));
+    } else if !self.username.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-'){
+      errors.push(FieldError::new("username", "may only contain letters, digits, _ and -"));
+    }
+    if self.password.chars().count() < MIN_PASSWORD_LENGTH{
+      errors.push(FieldError::new("password", &format!("must be at least {} characters", MIN_PASSWORD_LENGTH)));
+    }
+    match errors.is_empty(){
+      true => Ok(()),
+      false => Err(errors)
+    }
+  }
+}
+
+impl PostPatch{
+  /// Trims every field and checks it is non-empty and within its maximum
+  /// length. Unless `partial`, every field must be present.
+  pub fn validate(self, partial: bool) -> Result<PostPatch, Vec<FieldError>>{
+    let mut errors = vec![];
+    let mut check = |field: &'static str, value: Option<String>, max: usize| -> Option<String>{
+      let value = match value{
+        Some(value) => value.trim().to_string(),
+        None if partial => return None,
+        None => String::new()
+      };
+      if value.is_empty(){
+        errors.push(FieldError::new(field, "is required"));
+      } else if value.chars().count() > max{
+        errors.push(FieldError::new(field, &format!("must be at most {} characters", max)));
+      }
+      Some(value)
+    };
+    let patch = PostPatch{
+      title: check("title", self.title, MAX_TITLE_LENGTH),
+      body: check("body", self.body, MAX_BODY_LENGTH)
+    };
+    match errors.is_empty(){
+      true => Ok(patch),
+      false => Err(errors)
+    }
+  }
+}
+
 impl Post{
   pub fn new(title: &str,
     body: &str,
@@ -27,7 +143,77 @@ impl Post{
       }
   }
 
+  /// New post by `author` from validated fields, with a fresh uuid and the
+  /// current time.
+  pub fn create(fields: PostPatch, author: &str) -> Post{
+    Post{
+      title: fields.title.unwrap_or_default(),
+      body: fields.body.unwrap_or_default(),
+      author: author.to_string(),
+      datetime: Utc::now(),
+      uuid: Uuid::new_v4()
+    }
+  }
+
   pub fn uuid(&self) -> &Uuid{
     &self.uuid
   }
+
+  pub fn title(&self) -> &str{
+    &self.title
+  }
+
+  pub fn body(&self) -> &str{
+    &self.body
+  }
+
+  pub fn author(&self) -> &str{
+    &self.author
+  }
+
+  pub fn datetime(&self) -> &DateTime<Utc>{
+    &self.datetime
+  }
+
+  pub fn apply(&mut self, patch: PostPatch){
+    if let Some(title) = patch.title{
+      self.title = title;
+    }
+    if let Some(body) = patch.body{
+      self.body = body;
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests{
+  use super::*;
+
+  #[test]
+  fn test_validation(){
+    let fields: PostPatch = serde_json::from_str(
+      r#"{"title": "  Hello  ", "body": "Body", "author": "Fatbrad", "uuid": "not trusted"}"#
+    ).unwrap();
+    let post = Post::create(fields.validate(false).unwrap(), "Raiden");
+    assert_eq!((post.title(), post.author()), ("Hello", "Raiden"));
+
+    let long = "x".repeat(MAX_TITLE_LENGTH + 1);
+    let fields = PostPatch {title: Some(long), body: Some("   ".to_string())};
+    let fields = fields.validate(false).unwrap_err().into_iter().map(|e| e.field).collect::<Vec<_>>();
+    assert_eq!(fields, ["title", "body"]);
+    let fields = PostPatch::default().validate(false).unwrap_err().into_iter().map(|e| e.field).collect::<Vec<_>>();
+    assert_eq!(fields, ["title", "body"]);
+
+    let patch = PostPatch {body: Some(" New ".to_string()), ..PostPatch::default()};
+    assert_eq!(patch.validate(true).unwrap().body.as_deref(), Some("New"));
+  }
+
+  #[test]
+  fn test_credentials(){
+    let valid = Credentials {username: "raiden_1".to_string(), password: "correct horse".to_string()};
+    assert!(valid.validate().is_ok());
+    let invalid = Credentials {username: "no spaces".to_string(), password: "short".to_string()};
+    let fields = invalid.validate().unwrap_err().into_iter().map(|e| e.field).collect::<Vec<_>>();
+    assert_eq!(fields, ["username", "password"]);
+  }
 }
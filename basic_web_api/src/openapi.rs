@@ -0,0 +1,366 @@
+use schemars::gen::{SchemaGenerator, SchemaSettings};
+use schemars::JsonSchema;
+use serde_json::{json, Map, Value};
+
+use crate::error::ApiError;
+use crate::feed::{FeedPage, DEFAULT_LIMIT, MAX_LIMIT};
+use crate::models::{Comment, CommentFields, Credentials, Post, PostPatch};
+use crate::routes::{Route, ROUTES};
+use crate::search::SearchPage;
+
+/// What `operation` knows about a route beyond its method and path.
+struct Operation{
+  summary: &'static str,
+  secured: bool,
+  request: Option<Value>,
+  responses: Vec<(u16, &'static str, Option<Value>)>
+}
+
+/// OpenAPI 3 document for every entry of `ROUTES`, with schemas generated
+/// from the models.
+pub fn spec() -> Value{
+  let mut gen = SchemaSettings::openapi3().into_generator();
+  let mut paths = Map::new();
+  for route in ROUTES{
+    let operation = operation(route, &mut gen).unwrap_or_else(|| panic!("route {} is not documented", route.name));
+    let path = paths.entry(openapi_path(route.path)).or_insert_with(|| json!({}));
+    path[route.method.as_ref().to_lowercase()] = render(route, operation);
+  }
+
+  json!({
+    "openapi": "3.0.3",
+    "info": {"title": "basic_web_api", "version": env!("CARGO_PKG_VERSION")},
+    "paths": paths,
+    "components": {
+      "schemas": gen.definitions(),
+      "securitySchemes": {"bearer": {"type": "http", "scheme": "bearer"}}
+    }
+  })
+}
+
+fn schema<T: JsonSchema>(gen: &mut SchemaGenerator) -> Value{
+  serde_json::to_value(gen.subschema_for::<T>()).unwrap()
+}
+
+fn operation(route: &Route, gen: &mut SchemaGenerator) -> Option<Operation>{
+  let error = schema::<ApiError>(gen);
+  let failure = |status: u16, description: &'static str| (status, description, Some(error.clone()));
+  // `AuthMiddleware`, `BodyLimit` and `RateLimiter` run before every route.
+  let operation = |summary, secured, request, mut responses: Vec<_>|{
+    if !responses.iter().any(|(status, _, _)| *status == 401){
+      responses.push(failure(401, "Invalid token"));
+    }
+    responses.push(failure(413, "Body too large"));
+    responses.push(failure(429, "Rate limit exceeded"));
+    Operation {summary, secured, request, responses}
+  };
+  let username = json!({"type": "object", "required": ["username"], "properties": {"username": {"type": "string"}}});
+  let session = json!({
+    "type": "object",
+    "required": ["username", "token"],
+    "properties": {"username": {"type": "string"}, "token": {"type": "string"}}
+  });
+
+  let operation = match route.name{
+    "post_feed" => operation("List posts", false, None, vec![
+      (200, "A page of posts", Some(schema::<FeedPage>(gen))),
+      (304, "The page still has the ETag named in If-None-Match", None),
+      failure(400, "Invalid query parameters")
+    ]),
+    "search" => operation("Search posts by relevance", false, None, vec![
+      (200, "Matching posts, best first, with highlighted snippets", Some(schema::<SearchPage>(gen))),
+      failure(400, "Missing q or invalid query parameters")
+    ]),
+    "post_post" => operation("Create a post by the current user", true, Some(schema::<PostPatch>(gen)), vec![
+      (201, "The created post", Some(schema::<Post>(gen))),
+      failure(400, "Malformed JSON"),
+      failure(401, "Missing or invalid token"),
+      failure(422, "Invalid fields")
+    ]),
+    "post" => operation("Get a post", false, None, vec![
+      (200, "The post", Some(schema::<Post>(gen))),
+      (304, "The post still has the ETag named in If-None-Match", None),
+      failure(400, "Malformed id"),
+      failure(404, "Unknown post")
+    ]),
+    "put_post" => operation("Replace the title and body of an own post", true, Some(schema::<PostPatch>(gen)), vec![
+      (200, "The updated post", Some(schema::<Post>(gen))),
+      failure(400, "Malformed id or JSON"),
+      failure(401, "Missing or invalid token"),
+      failure(403, "Post of another author"),
+      failure(404, "Unknown post"),
+      failure(412, "The post changed since the ETag in If-Match"),
+      failure(422, "Invalid fields"),
+      failure(428, "If-Match is missing")
+    ]),
+    "patch_post" => operation("Change some fields of an own post", true, Some(schema::<PostPatch>(gen)), vec![
+      (200, "The updated post", Some(schema::<Post>(gen))),
+      failure(400, "Malformed id or JSON"),
+      failure(401, "Missing or invalid token"),
+      failure(403, "Post of another author"),
+      failure(404, "Unknown post"),
+      failure(412, "The post changed since the ETag in If-Match"),
+      failure(422, "Invalid fields"),
+      failure(428, "If-Match is missing")
+    ]),
+    "delete_post" => operation("Delete an own post and its comments", true, None, vec![
+      (204, "Deleted", None),
+      failure(400, "Malformed id"),
+      failure(401, "Missing or invalid token"),
+      failure(403, "Post of another author"),
+      failure(404, "Unknown post")
+    ]),
+    "comments" => operation("List the comments on a post", false, None, vec![
+      (200, "Comments, oldest first", Some(json!({"type": "array", "items": schema::<Comment>(gen)}))),
+      failure(400, "Malformed id"),
+      failure(404, "Unknown post")
+    ]),
+    "post_comment" => operation("Comment on a post", true, Some(schema::<CommentFields>(gen)), vec![
+      (201, "The created comment", Some(schema::<Comment>(gen))),
+      failure(400, "Malformed id or JSON"),
+      failure(401, "Missing or invalid token"),
+      failure(404, "Unknown post"),
+      failure(422, "Invalid fields")
+    ]),
+    "delete_comment" => operation("Delete an own comment or a comment on an own post", true, None, vec![
+      (204, "Deleted", None),
+      failure(400, "Malformed id"),
+      failure(401, "Missing or invalid token"),
+      failure(403, "Comment of another author"),
+      failure(404, "Unknown post or comment")
+    ]),
+    "register" => operation("Create a user", false, Some(schema::<Credentials>(gen)), vec![
+      (201, "The created user", Some(username)),
+      failure(400, "Malformed JSON"),
+      failure(409, "Username taken"),
+      failure(422, "Invalid fields")
+    ]),
+    "login" => operation("Get a bearer token", false, Some(schema::<Credentials>(gen)), vec![
+      (200, "A new token", Some(session)),
+      failure(400, "Malformed JSON"),
+      failure(401, "Wrong username or password, or invalid token")
+    ]),
+    "healthz" => operation("Health check", false, None, vec![
+      (200, "The server and its storage are up", Some(json!({"type": "object", "properties": {"status": {"type": "string"}}}))),
+      failure(503, "Storage is unavailable")
+    ]),
+    "metrics" => operation("Request metrics in the Prometheus text format", false, None, vec![
+      (200, "Counters, latency histograms and the post count", Some(json!({"type": "string"})))
+    ]),
+    "openapi" => operation("This document", false, None, vec![
+      (200, "OpenAPI 3 document", Some(json!({"type": "object"})))
+    ]),
+    _ => return None
+  };
+  Some(operation)
+}
+
+fn render(route: &Route, operation: Operation) -> Value{
+  let mut parameters: Vec<Value> = route.path
+    .split('/')
+    .filter_map(|segment| segment.strip_prefix(':'))
+    .map(|name| json!({"name": name, "in": "path", "required": true, "schema": {"type": "string", "format": "uuid"}}))
+    .collect();
+  match route.name{
+    "post_feed" => parameters.extend(feed_parameters()),
+    "search" => parameters.extend(search_parameters()),
+    _ => {}
+  }
+  let tagged = match route.name{
+    "post_feed" | "post" => {
+      parameters.push(header("If-None-Match", false, "ETag of a cached copy"));
+      true
+    },
+    "put_post" | "patch_post" => {
+      parameters.push(header("If-Match", true, "ETag the update was made against, or *"));
+      true
+    },
+    "post_post" => true,
+    _ => false
+  };
+
+  let responses: Map<String, Value> = operation.responses.into_iter().map(|(status, description, body)|{
+    let mut response = json!({"description": description});
+    if let Some(body) = body{
+      let media = if route.name == "metrics" {"text/plain"} else {"application/json"};
+      response["content"] = json!({media: {"schema": body}});
+      if tagged && status < 300{
+        response["headers"] = json!({"ETag": {"description": "Tag of the returned content", "schema": {"type": "string"}}});
+      }
+    }
+    (status.to_string(), response)
+  }).collect();
+
+  let mut rendered = json!({"operationId": route.name, "summary": operation.summary, "responses": responses});
+  if !parameters.is_empty(){
+    rendered["parameters"] = json!(parameters);
+  }
+  if let Some(request) = operation.request{
+    rendered["requestBody"] = json!({"required": true, "content": {"application/json": {"schema": request}}});
+  }
+  if operation.secured{
+    rendered["security"] = json!([{"bearer": []}]);
+  }
+  rendered
+}
+
+fn header(name: &str, required: bool, description: &str) -> Value{
+  json!({"name": name, "in": "header", "required": required, "description": description, "schema": {"type": "string"}})
+}
+
+fn feed_parameters() -> Vec<Value>{
+  let query = |name: &str, description: &str, schema: Value|{
+    json!({"name": name, "in": "query", "required": false, "description": description, "schema": schema})
+  };
+  vec![
+    query("limit", "Posts per page", json!({"type": "integer", "minimum": 1, "maximum": MAX_LIMIT, "default": DEFAULT_LIMIT})),
+    query("cursor", "`next_cursor` of the previous page", json!({"type": "string"})),
+    query("author", "Only posts by this author", json!({"type": "string"})),
+    query("since", "Only posts at or after this time", json!({"type": "string", "format": "date-time"})),
+    query("until", "Only posts at or before this time", json!({"type": "string", "format": "date-time"})),
+    query("q", "Case insensitive text in the title or body", json!({"type": "string"})),
+    query("sort", "Oldest first with datetime, newest first with -datetime", json!({"type": "string", "enum": ["datetime", "-datetime"], "default": "-datetime"}))
+  ]
+}
+
+fn search_parameters() -> Vec<Value>{
+  vec![
+    json!({"name": "q", "in": "query", "required": true, "description": "Words to look for in the title and body", "schema": {"type": "string"}}),
+    json!({"name": "limit", "in": "query", "required": false, "description": "Hits per page", "schema": {"type": "integer", "minimum": 1, "maximum": MAX_LIMIT, "default": DEFAULT_LIMIT}}),
+    json!({"name": "offset", "in": "query", "required": false, "description": "Hits to skip", "schema": {"type": "integer", "minimum": 0, "default": 0}})
+  ]
+}
+
+/// `/post/:id` in OpenAPI notation, `/post/{id}`.
+fn openapi_path(path: &str) -> String{
+  path.split('/')
+    .map(|segment| match segment.strip_prefix(':'){
+      Some(name) => format!("{{{}}}", name),
+      None => segment.to_string()
+    })
+    .collect::<Vec<_>>()
+    .join("/")
+}
+
+#[cfg(test)]
+mod tests{
+  use super::*;
+  use iron::method::Method;
+  use iron::prelude::Chain;
+  use uuid::Uuid;
+  use crate::routes;
+  use crate::testing::{self, send, MAX_BODY_BYTES};
+
+  const SNAPSHOT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/openapi.json");
+
+  /// `openapi.json` is the published spec. Changing a route or a model
+  /// without regenerating it, with `UPDATE_OPENAPI=1 cargo test`, fails here.
+  #[test]
+  fn test_spec_is_up_to_date(){
+    let spec = spec();
+    if std::env::var_os("UPDATE_OPENAPI").is_some(){
+      std::fs::write(SNAPSHOT, serde_json::to_string_pretty(&spec).unwrap() + "\n").unwrap();
+    }
+    let published: Value = serde_json::from_str(&std::fs::read_to_string(SNAPSHOT).unwrap()).unwrap();
+    assert!(spec == published, "openapi.json is stale, regenerate it with UPDATE_OPENAPI=1 cargo test");
+  }
+
+  #[test]
+  fn test_every_route_is_documented(){
+    let spec = spec();
+    for route in ROUTES{
+      let operation = &spec["paths"][openapi_path(route.path)][route.method.as_ref().to_lowercase()];
+      assert_eq!(operation["operationId"], route.name);
+    }
+    assert_eq!(openapi_path("/post/:id/comments/:cid"), "/post/{id}/comments/{cid}");
+  }
+
+  /// Sends a request through the served chain and checks both the status and
+  /// that the spec lists it for the route.
+  fn check(app: &Chain, method: &str, path: &str, headers: &[(&str, &str)], body: &str, expected: u16){
+    let status = send(app, method, path, headers, body).status.to_u16();
+    assert_eq!(status, expected, "{} {}", method, path);
+
+    let segments: Vec<&str> = path.split('?').next().unwrap().split('/').skip(1).collect();
+    let route = routes::find(&method.parse::<Method>().unwrap(), &segments).unwrap();
+    let responses = &spec()["paths"][openapi_path(route.path)][method.to_lowercase()]["responses"];
+    assert!(responses[expected.to_string()].is_object(), "{} {} returns undocumented {}", method, path, expected);
+  }
+
+  #[test]
+  fn test_failures_are_documented(){
+    let app = testing::app();
+    let alice = testing::login(&app, "alice");
+    let bob = testing::login(&app, "bob");
+    let (alice, bob) = (("Authorization", alice.as_str()), ("Authorization", bob.as_str()));
+    let id = testing::create_post(&app, alice.1)["uuid"].as_str().unwrap().to_string();
+    let comment = send(&app, "POST", &format!("/post/{}/comments", id), &[alice], r#"{"body": "Hi"}"#).json();
+    let cid = comment["uuid"].as_str().unwrap();
+    let unknown = Uuid::new_v4().to_string();
+    let post = |id: &str| format!("/post/{}", id);
+    let comments = |id: &str| format!("/post/{}/comments", id);
+    let too_large = format!(r#"{{"body": "{}"}}"#, "x".repeat(MAX_BODY_BYTES as usize));
+    let fields = r#"{"title": "New", "body": "Text"}"#;
+
+    check(&app, "GET", "/post_feed?limit=0", &[], "", 400);
+    check(&app, "GET", "/search", &[], "", 400);
+
+    check(&app, "POST", "/post", &[], fields, 401);
+    check(&app, "POST", "/post", &[alice], "{", 400);
+    check(&app, "POST", "/post", &[alice], &too_large, 413);
+    check(&app, "POST", "/post", &[alice], r#"{"title": ""}"#, 422);
+
+    check(&app, "GET", &post("nope"), &[], "", 400);
+    check(&app, "GET", &post(&unknown), &[], "", 404);
+
+    let etag = send(&app, "GET", &post(&id), &[], "").headers.get_raw("ETag").unwrap()[0].clone();
+    let if_match = ("If-Match", std::str::from_utf8(&etag).unwrap());
+    for method in ["PUT", "PATCH"]{
+      check(&app, method, &post("nope"), &[alice, if_match], fields, 400);
+      check(&app, method, &post(&id), &[if_match], fields, 401);
+      check(&app, method, &post(&id), &[alice, if_match], "{", 400);
+      check(&app, method, &post(&id), &[alice, if_match], &too_large, 413);
+      check(&app, method, &post(&id), &[bob, if_match], fields, 403);
+      check(&app, method, &post(&unknown), &[alice, if_match], fields, 404);
+      check(&app, method, &post(&id), &[alice, ("If-Match", "\"stale\"")], fields, 412);
+      check(&app, method, &post(&id), &[alice, if_match], r#"{"title": ""}"#, 422);
+      check(&app, method, &post(&id), &[alice], fields, 428);
+    }
+
+    check(&app, "GET", &comments("nope"), &[], "", 400);
+    check(&app, "GET", &comments(&unknown), &[], "", 404);
+    check(&app, "POST", &comments("nope"), &[alice], r#"{"body": "Hi"}"#, 400);
+    check(&app, "POST", &comments(&id), &[alice], "{", 400);
+    check(&app, "POST", &comments(&id), &[], r#"{"body": "Hi"}"#, 401);
+    check(&app, "POST", &comments(&unknown), &[alice], r#"{"body": "Hi"}"#, 404);
+    check(&app, "POST", &comments(&id), &[alice], &too_large, 413);
+    check(&app, "POST", &comments(&id), &[alice], r#"{"body": " "}"#, 422);
+    check(&app, "DELETE", &format!("/post/{}/comments/nope", id), &[alice], "", 400);
+    check(&app, "DELETE", &format!("/post/{}/comments/{}", id, cid), &[], "", 401);
+    check(&app, "DELETE", &format!("/post/{}/comments/{}", id, cid), &[bob], "", 403);
+    check(&app, "DELETE", &format!("/post/{}/comments/{}", id, unknown), &[alice], "", 404);
+
+    check(&app, "DELETE", &post("nope"), &[alice], "", 400);
+    check(&app, "DELETE", &post(&id), &[], "", 401);
+    check(&app, "DELETE", &post(&id), &[bob], "", 403);
+    check(&app, "DELETE", &post(&unknown), &[alice], "", 404);
+
+    let credentials = r#"{"username": "alice", "password": "correct horse"}"#;
+    check(&app, "POST", "/register", &[], "{", 400);
+    check(&app, "POST", "/register", &[], credentials, 409);
+    check(&app, "POST", "/register", &[], &too_large, 413);
+    check(&app, "POST", "/register", &[], r#"{"username": "a", "password": "short"}"#, 422);
+    check(&app, "POST", "/login", &[], "{", 400);
+    check(&app, "POST", "/login", &[], r#"{"username": "alice", "password": "wrong horse"}"#, 401);
+    check(&app, "POST", "/login", &[], &too_large, 413);
+
+    let limited = testing::throttled_app();
+    send(&limited, "GET", "/healthz", &[], "");
+    for route in ROUTES{
+      let path = route.path.replace(":id", &unknown).replace(":cid", &unknown);
+      check(&app, route.method.as_ref(), &path, &[("Authorization", "Bearer invalid")], "", 401);
+      check(&app, route.method.as_ref(), &path, &[], &too_large, 413);
+      check(&limited, route.method.as_ref(), &path, &[], "", 429);
+    }
+  }
+}
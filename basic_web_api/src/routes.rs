@@ -0,0 +1,100 @@
+use iron::method::Method;
+use iron::Handler;
+use router::Router;
+
+use crate::handlers::Handlers;
+
+pub struct Route{
+  pub method: Method,
+  pub path: &'static str,
+  pub name: &'static str
+}
+
+const fn route(method: Method, path: &'static str, name: &'static str) -> Route{
+  Route {method, path, name}
+}
+
+/// Every route the API serves. `router` registers the handlers by name from
+/// this table and `openapi` documents each entry, so neither can miss one.
+pub const ROUTES: &[Route] = &[
+  route(Method::Get, "/post_feed", "post_feed"),
+  route(Method::Get, "/search", "search"),
+  route(Method::Post, "/post", "post_post"),
+  route(Method::Get, "/post/:id", "post"),
+  route(Method::Put, "/post/:id", "put_post"),
+  route(Method::Patch, "/post/:id", "patch_post"),
+  route(Method::Delete, "/post/:id", "delete_post"),
+  route(Method::Get, "/post/:id/comments", "comments"),
+  route(Method::Post, "/post/:id/comments", "post_comment"),
+  route(Method::Delete, "/post/:id/comments/:cid", "delete_comment"),
+  route(Method::Post, "/register", "register"),
+  route(Method::Post, "/login", "login"),
+  route(Method::Get, "/openapi.json", "openapi"),
+  route(Method::Get, "/healthz", "healthz"),
+  route(Method::Get, "/metrics", "metrics")
+];
+
+pub fn router(handlers: Handlers) -> Router{
+  let mut router = Router::new();
+  add(&mut router, "post_feed", handlers.post_feed);
+  add(&mut router, "search", handlers.search);
+  add(&mut router, "post_post", handlers.post_post);
+  add(&mut router, "post", handlers.post);
+  add(&mut router, "put_post", handlers.put_post);
+  add(&mut router, "patch_post", handlers.patch_post);
+  add(&mut router, "delete_post", handlers.delete_post);
+  add(&mut router, "comments", handlers.comments);
+  add(&mut router, "post_comment", handlers.post_comment);
+  add(&mut router, "delete_comment", handlers.delete_comment);
+  add(&mut router, "register", handlers.register);
+  add(&mut router, "login", handlers.login);
+  add(&mut router, "openapi", handlers.openapi);
+  add(&mut router, "healthz", handlers.healthz);
+  add(&mut router, "metrics", handlers.metrics);
+  router
+}
+
+/// Route matching `method` and the segments of a request path, such as
+/// `["post", "<uuid>"]` for `/post/:id`.
+pub fn find(method: &Method, path: &[&str]) -> Option<&'static Route>{
+  ROUTES.iter().find(|route|{
+    let pattern: Vec<&str> = route.path.split('/').skip(1).collect();
+    &route.method == method
+      && pattern.len() == path.len()
+      && pattern.iter().zip(path).all(|(p, s)| p.starts_with(':') || p == s)
+  })
+}
+
+/// Registers `handler` under the method and path of the named route.
+fn add<H: Handler>(router: &mut Router, name: &'static str, handler: H){
+  match ROUTES.iter().find(|r| r.name == name){
+    Some(route) => {
+      router.route(route.method.clone(), route.path, handler, name);
+    },
+    None => panic!("route {} is missing from ROUTES", name)
+  }
+}
+
+#[cfg(test)]
+mod tests{
+  use super::*;
+  use std::sync::{Arc, Mutex};
+  use crate::config::Backend;
+  use crate::database;
+  use crate::metrics::Metrics;
+
+  #[test]
+  fn test_every_handler_has_a_route(){
+    let database = database::open(&Backend::Memory).unwrap();
+    router(Handlers::new(Arc::new(Mutex::new(database)), Metrics::new()));
+    for (i, route) in ROUTES.iter().enumerate(){
+      assert!(ROUTES[..i].iter().all(|r| r.name != route.name), "duplicate route {}", route.name);
+    }
+
+    let id = "6f1c2b64-7a63-4d3b-9a4e-2f4d0f3c1a77";
+    assert_eq!(find(&Method::Patch, &["post", id]).map(|r| r.name), Some("patch_post"));
+    assert_eq!(find(&Method::Delete, &["post", id, "comments", id]).map(|r| r.name), Some("delete_comment"));
+    assert!(find(&Method::Get, &["post", id, "likes"]).is_none());
+    assert!(find(&Method::Post, &["post_feed"]).is_none());
+  }
+}
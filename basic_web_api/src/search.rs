@@ -0,0 +1,311 @@
+use std::collections::{HashMap, HashSet};
+use schemars::JsonSchema;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::feed::{DEFAULT_LIMIT, MAX_LIMIT};
+use crate::models::Post;
+
+/// A title match counts as much as this many body matches.
+const TITLE_WEIGHT: f64 = 2.0;
+/// BM25 term frequency saturation and length normalization.
+const K1: f64 = 1.2;
+const B: f64 = 0.75;
+/// Words of body text around the first match in a snippet.
+const SNIPPET_WORDS: usize = 24;
+
+/// A lowercased word and its byte range in the original text.
+struct Token<'a>{
+  term: String,
+  start: usize,
+  end: usize,
+  text: &'a str
+}
+
+/// Splits `text` into words of letters and digits.
+fn tokenize(text: &str) -> Vec<Token<'_>>{
+  let mut tokens = Vec::new();
+  let mut start = None;
+  for (i, c) in text.char_indices().chain(std::iter::once((text.len(), ' '))){
+    match (start, c.is_alphanumeric()){
+      (None, true) => start = Some(i),
+      (Some(s), false) => {
+        tokens.push(Token {term: text[s..i].to_lowercase(), start: s, end: i, text: &text[s..i]});
+        start = None;
+      },
+      _ => {}
+    }
+  }
+  tokens
+}
+
+/// Distinct terms of a search query.
+fn terms(query: &str) -> HashSet<String>{
+  tokenize(query).into_iter().map(|t| t.term).collect()
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct Frequency{
+  title: u32,
+  body: u32
+}
+
+impl Frequency{
+  fn weighted(&self) -> f64{
+    TITLE_WEIGHT * self.title as f64 + self.body as f64
+  }
+}
+
+#[derive(Clone, Debug, Default)]
+struct Document{
+  length: f64,
+  terms: Vec<String>
+}
+
+/// Inverted index from terms to the posts containing them. The storage
+/// backends update it on every post write and rank searches with it, BM25
+/// over the title and body with title matches weighted higher.
+#[derive(Clone, Debug, Default)]
+pub struct SearchIndex{
+  postings: HashMap<String, HashMap<Uuid, Frequency>>,
+  documents: HashMap<Uuid, Document>,
+  total_length: f64
+}
+
+impl SearchIndex{
+  /// Indexes `post`, replacing an earlier version of it.
+  pub fn insert(&mut self, post: &Post){
+    self.remove(post.uuid());
+    let mut frequencies: HashMap<String, Frequency> = HashMap::new();
+    let title = tokenize(post.title());
+    let body = tokenize(post.body());
+    for token in &title{
+      frequencies.entry(token.term.clone()).or_default().title += 1;
+    }
+    for token in &body{
+      frequencies.entry(token.term.clone()).or_default().body += 1;
+    }
+
+    let length = TITLE_WEIGHT * title.len() as f64 + body.len() as f64;
+    let terms = frequencies.keys().cloned().collect();
+    for (term, frequency) in frequencies{
+      self.postings.entry(term).or_default().insert(*post.uuid(), frequency);
+    }
+    self.documents.insert(*post.uuid(), Document {length, terms});
+    self.total_length += length;
+  }
+
+  pub fn remove(&mut self, id: &Uuid){
+    let document = match self.documents.remove(id){
+      Some(document) => document,
+      None => return
+    };
+    self.total_length -= document.length;
+    for term in document.terms{
+      if let Some(posts) = self.postings.get_mut(&term){
+        posts.remove(id);
+        if posts.is_empty(){
+          self.postings.remove(&term);
+        }
+      }
+    }
+  }
+
+  /// Ids of the posts matching any term of `query`, best match first.
+  pub fn search(&self, query: &str) -> Vec<(Uuid, f64)>{
+    let count = self.documents.len() as f64;
+    let average = self.total_length / count.max(1.0);
+    let mut scores: HashMap<Uuid, f64> = HashMap::new();
+    for term in terms(query){
+      let posts = match self.postings.get(&term){
+        Some(posts) => posts,
+        None => continue
+      };
+      let matching = posts.len() as f64;
+      let idf = (1.0 + (count - matching + 0.5) / (matching + 0.5)).ln();
+      for (id, frequency) in posts{
+        let tf = frequency.weighted();
+        let length = self.documents[id].length;
+        let norm = K1 * (1.0 - B + B * length / average.max(1.0));
+        *scores.entry(*id).or_default() += idf * tf * (K1 + 1.0) / (tf + norm);
+      }
+    }
+
+    let mut ranked: Vec<(Uuid, f64)> = scores.into_iter().collect();
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+    ranked
+  }
+}
+
+/// Query parameters of `GET /search`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SearchQuery{
+  pub text: String,
+  pub limit: usize,
+  pub offset: usize
+}
+
+impl SearchQuery{
+  /// Parses `q`, which is required, `limit` and `offset`. Unknown
+  /// parameters are ignored.
+  pub fn parse<'a, I>(pairs: I) -> Result<SearchQuery, String>
+    where I: IntoIterator<Item = (&'a str, &'a str)>{
+    let mut query = SearchQuery {text: String::new(), limit: DEFAULT_LIMIT, offset: 0};
+    for (key, value) in pairs{
+      match key{
+        "q" => query.text = value.to_string(),
+        "limit" => {
+          query.limit = match value.parse::<usize>(){
+            Ok(limit) if (1..=MAX_LIMIT).contains(&limit) => limit,
+            _ => return Err(format!("limit must be between 1 and {}", MAX_LIMIT))
+          }
+        },
+        "offset" => query.offset = value.parse().map_err(|_| "offset must be a non-negative integer")?,
+        _ => {}
+      }
+    }
+    if terms(&query.text).is_empty(){
+      return Err("q must contain at least one word".to_string());
+    }
+    Ok(query)
+  }
+}
+
+/// Matched text with every query term wrapped in `<mark>`. Everything else
+/// is HTML escaped, so snippets can be inserted into a page as they are.
+#[derive(Clone, Debug, PartialEq, Serialize, JsonSchema)]
+pub struct Highlights{
+  pub title: String,
+  /// Words around the first match in the body, `…` marks cut text.
+  pub body: String
+}
+
+#[derive(Clone, Debug, Serialize, JsonSchema)]
+pub struct SearchHit{
+  #[serde(flatten)]
+  pub post: Post,
+  pub score: f64,
+  pub highlights: Highlights
+}
+
+#[derive(Clone, Debug, Serialize, JsonSchema)]
+pub struct SearchPage{
+  pub hits: Vec<SearchHit>,
+  /// Number of matching posts across all pages.
+  pub total: usize,
+  pub limit: usize,
+  pub offset: usize
+}
+
+impl SearchPage{
+  /// Page of `ranked` selected by `query`, loading each post with `fetch`.
+  pub fn collect<E, F>(query: &SearchQuery, ranked: Vec<(Uuid, f64)>, mut fetch: F) -> Result<SearchPage, E>
+    where F: FnMut(&Uuid) -> Result<Option<Post>, E>{
+    let terms = terms(&query.text);
+    let total = ranked.len();
+    let mut hits = Vec::new();
+    for (id, score) in ranked.into_iter().skip(query.offset).take(query.limit){
+      if let Some(post) = fetch(&id)?{
+        let highlights = Highlights{
+          title: highlight(post.title(), &tokenize(post.title()), &terms),
+          body: snippet(post.body(), &terms)
+        };
+        hits.push(SearchHit {post, score, highlights});
+      }
+    }
+    Ok(SearchPage {hits, total, limit: query.limit, offset: query.offset})
+  }
+}
+
+fn snippet(text: &str, terms: &HashSet<String>) -> String{
+  let tokens = tokenize(text);
+  if tokens.len() <= SNIPPET_WORDS{
+    return highlight(text, &tokens, terms);
+  }
+  let first = tokens.iter().position(|t| terms.contains(&t.term)).unwrap_or(0);
+  let start = first.saturating_sub(SNIPPET_WORDS / 3).min(tokens.len() - SNIPPET_WORDS);
+  let end = start + SNIPPET_WORDS;
+  let window = &text[tokens[start].start..tokens[end - 1].end];
+  let mut snippet = highlight(window, &tokenize(window), terms);
+  if start > 0{
+    snippet.insert(0, '…');
+  }
+  if end < tokens.len(){
+    snippet.push('…');
+  }
+  snippet
+}
+
+fn highlight(text: &str, tokens: &[Token], terms: &HashSet<String>) -> String{
+  let mut marked = String::with_capacity(text.len());
+  let mut written = 0;
+  for token in tokens.iter().filter(|t| terms.contains(&t.term)){
+    escape_into(&mut marked, &text[written..token.start]);
+    marked.push_str("<mark>");
+    escape_into(&mut marked, token.text);
+    marked.push_str("</mark>");
+    written = token.end;
+  }
+  escape_into(&mut marked, &text[written..]);
+  marked
+}
+
+fn escape_into(out: &mut String, text: &str){
+  for c in text.chars(){
+    match c{
+      '&' => out.push_str("&amp;"),
+      '<' => out.push_str("&lt;"),
+      '>' => out.push_str("&gt;"),
+      '"' => out.push_str("&quot;"),
+      c => out.push(c)
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests{
+  use super::*;
+  use chrono::Utc;
+
+  #[test]
+  fn test_highlights(){
+    let terms = terms("Rust");
+    assert_eq!(highlight("Rust & <rust>!", &tokenize("Rust & <rust>!"), &terms), "<mark>Rust</mark> &amp; &lt;<mark>rust</mark>&gt;!");
+
+    let body = (0..100).map(|i| if i == 50 {"rust".to_string()} else {format!("w{}", i)}).collect::<Vec<_>>().join(" ");
+    let snippet = snippet(&body, &terms);
+    assert!(snippet.starts_with("…w42 ") && snippet.ends_with(" w65…"), "{}", snippet);
+    assert!(snippet.contains(" <mark>rust</mark> "));
+  }
+
+  #[test]
+  fn test_index_ranks_and_updates(){
+    let post = |title: &str, body: &str| Post::new(title, body, "Raiden", Utc::now(), Uuid::new_v4());
+    let in_title = post("Rust web servers", "Notes on iron");
+    let in_body = post("Web servers", "Written in rust");
+    let other = post("Gardening", "Tomatoes");
+    let mut index = SearchIndex::default();
+    for p in [&in_title, &in_body, &other]{
+      index.insert(p);
+    }
+
+    let ids = |index: &SearchIndex, q: &str| index.search(q).into_iter().map(|(id, _)| id).collect::<Vec<_>>();
+    assert_eq!(ids(&index, "RUST"), [*in_title.uuid(), *in_body.uuid()]);
+    assert_eq!(ids(&index, "tomatoes rust").len(), 3);
+    assert!(ids(&index, "python").is_empty());
+
+    index.insert(&Post::new("Gardening", "Rust on tomatoes", "Raiden", Utc::now(), *other.uuid()));
+    assert_eq!(ids(&index, "tomatoes"), [*other.uuid()]);
+    index.remove(in_title.uuid());
+    assert_eq!(ids(&index, "iron"), Vec::<Uuid>::new());
+    assert_eq!(index.search("rust").len(), 2);
+  }
+
+  #[test]
+  fn test_query(){
+    let query = SearchQuery::parse([("q", "rust"), ("limit", "5"), ("offset", "10")]).unwrap();
+    assert_eq!((query.limit, query.offset), (5, 10));
+    assert!(SearchQuery::parse([("q", " ?! ")]).is_err());
+    assert!(SearchQuery::parse([("limit", "5")]).is_err());
+  }
+}
@@ -0,0 +1,37 @@
+use std::process;
+use std::sync::{Arc, Mutex};
+use log::{error, info};
+use signal_hook::consts::{SIGINT, SIGTERM};
+use signal_hook::iterator::Signals;
+
+use crate::database::Database;
+
+/// Blocks until SIGINT or SIGTERM, flushes `database` and exits.
+///
+/// The handler threads can't be stopped, so the database lock is taken and
+/// held until exit instead: requests still writing finish first and no later
+/// request gets to write.
+pub fn exit_on_signal(database: Arc<Mutex<Box<dyn Database>>>) -> !{
+  let mut signals = match Signals::new([SIGINT, SIGTERM]){
+    Ok(signals) => signals,
+    Err(e) => {
+      error!("can't install signal handlers: {}", e);
+      process::exit(1);
+    }
+  };
+  if let Some(signal) = signals.forever().next(){
+    info!("received signal {}, shutting down", signal);
+  }
+
+  let mut database = database.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+  match database.flush(){
+    Ok(()) => {
+      info!("storage flushed");
+      process::exit(0);
+    },
+    Err(e) => {
+      error!("can't flush storage: {}", e);
+      process::exit(1);
+    }
+  }
+}
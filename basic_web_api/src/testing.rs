@@ -0,0 +1,127 @@
+use std::io::{self, Cursor, Read, Write};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use hyper::buffer::BufReader;
+use hyper::net::NetworkStream;
+use iron::prelude::Chain;
+use iron::{status, Handler, Headers, Protocol, Request};
+use serde_json::{json, Value};
+
+use crate::config::{Backend, Limits};
+use crate::database;
+
+/// Limit on request bodies in `app`, small enough to exceed in a test.
+pub const MAX_BODY_BYTES: u64 = 1024;
+
+/// Request bytes read by hyper's parser, writes are discarded.
+struct MockStream(Cursor<Vec<u8>>);
+
+impl Read for MockStream{
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize>{
+    self.0.read(buf)
+  }
+}
+
+impl Write for MockStream{
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize>{
+    Ok(buf.len())
+  }
+
+  fn flush(&mut self) -> io::Result<()>{
+    Ok(())
+  }
+}
+
+impl NetworkStream for MockStream{
+  fn peer_addr(&mut self) -> io::Result<SocketAddr>{
+    Ok(addr())
+  }
+
+  fn set_read_timeout(&self, _: Option<Duration>) -> io::Result<()>{
+    Ok(())
+  }
+
+  fn set_write_timeout(&self, _: Option<Duration>) -> io::Result<()>{
+    Ok(())
+  }
+}
+
+fn addr() -> SocketAddr{
+  "127.0.0.1:8080".parse().unwrap()
+}
+
+pub struct TestResponse{
+  pub status: status::Status,
+  pub headers: Headers,
+  pub body: String
+}
+
+impl TestResponse{
+  pub fn json(&self) -> Value{
+    serde_json::from_str(&self.body).unwrap()
+  }
+}
+
+/// The served middleware chain over an empty in-memory database, with a
+/// rate limit no test reaches.
+pub fn app() -> Chain{
+  with_limits(Limits {requests_per_second: 1000.0, burst: 1000, max_body_bytes: MAX_BODY_BYTES})
+}
+
+/// Like `app`, but every request after the first is rate limited.
+pub fn throttled_app() -> Chain{
+  with_limits(Limits {requests_per_second: 0.001, burst: 1, max_body_bytes: MAX_BODY_BYTES})
+}
+
+fn with_limits(limits: Limits) -> Chain{
+  let database = database::open(&Backend::Memory).unwrap();
+  crate::app(&limits, Arc::new(Mutex::new(database)))
+}
+
+/// Sends a request through `app` as the server would, `headers` being
+/// `(name, value)` pairs.
+pub fn send(app: &Chain, method: &str, path: &str, headers: &[(&str, &str)], body: &str) -> TestResponse{
+  let mut raw = format!("{} {} HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n", method, path, body.len());
+  for (name, value) in headers{
+    raw.push_str(&format!("{}: {}\r\n", name, value));
+  }
+  raw.push_str("\r\n");
+  raw.push_str(body);
+
+  let mut stream = MockStream(Cursor::new(raw.into_bytes()));
+  let mut reader = BufReader::new(&mut stream as &mut dyn NetworkStream);
+  let http = hyper::server::Request::new(&mut reader, addr()).unwrap();
+  let mut req = Request::from_http(http, addr(), &Protocol::http()).unwrap();
+  let res = match app.handle(&mut req){
+    Ok(res) => res,
+    Err(err) => err.response
+  };
+
+  let mut body = Vec::new();
+  if let Some(mut writer) = res.body{
+    writer.write_body(&mut body).unwrap();
+  }
+  TestResponse{
+    status: res.status.unwrap_or(status::NotFound),
+    headers: res.headers,
+    body: String::from_utf8(body).unwrap()
+  }
+}
+
+/// Registers `username` and returns an `Authorization` header value with a
+/// fresh token.
+pub fn login(app: &Chain, username: &str) -> String{
+  let credentials = json!({"username": username, "password": "correct horse"}).to_string();
+  assert_eq!(send(app, "POST", "/register", &[], &credentials).status, status::Created);
+  let res = send(app, "POST", "/login", &[], &credentials);
+  assert_eq!(res.status, status::Ok);
+  format!("Bearer {}", res.json()["token"].as_str().unwrap())
+}
+
+/// Creates a post as the user of `auth` and returns it.
+pub fn create_post(app: &Chain, auth: &str) -> Value{
+  let res = send(app, "POST", "/post", &[("Authorization", auth)], r#"{"title": "Title", "body": "Body"}"#);
+  assert_eq!(res.status, status::Created);
+  res.json()
+}